@@ -1,8 +1,38 @@
-use ara_exec::manifest::execution_manifest::ExecutionManifest;
+pub mod credential;
+
+use crate::output::Pipes;
+use crate::resource_group;
+use anyhow::Result;
+use ara_exec::execution_client::{ExecutionError, DEFAULT_EXECUTION_ERROR, OOM_EXECUTION_ERROR};
+use ara_exec::manifest::execution_manifest::{
+    EnterExitTimeout, ExecutionManifest, Rlimit, RlimitResource, SchedulingPolicy,
+};
+use ara_exec::manifest::machine_manifest::MachineManifest;
+use credential::Credential;
 use libc::pid_t;
+use std::collections::{BTreeMap, HashMap};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// used if neither execution manifest nor machine manifest configures the timeout
+pub const DEFAULT_APPLICATION_TIMEOUT: u64 = 3; // seconds
 
+#[derive(Debug, Error)]
+pub enum ApplicationError {
+    #[error("Failed to launch {0} : {1}")]
+    LaunchFailed(String, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProcessState {
     Idle,
     Starting,
@@ -11,8 +41,640 @@ pub enum ProcessState {
     Terminated,
 }
 
+/// How a child terminated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// exit code
+    Exited(i32),
+    /// killing signal
+    Signaled(i32),
+    /// killed by the OOM killer of its resource group
+    OomKilled,
+    /// reaped by someone else
+    Lost,
+}
+
+impl ExitStatus {
+    fn from_raw(status: libc::c_int) -> Self {
+        if libc::WIFEXITED(status) {
+            ExitStatus::Exited(libc::WEXITSTATUS(status))
+        } else if libc::WIFSIGNALED(status) {
+            ExitStatus::Signaled(libc::WTERMSIG(status))
+        } else {
+            ExitStatus::Lost
+        }
+    }
+}
+
+/// A launched child, reaped by its own task as soon as it exits
+#[derive(Debug, Clone)]
+pub struct Child {
+    pub pid: pid_t,
+    // signals go through it, the pid may be reused once the child is reaped
+    pidfd: Arc<AsyncFd<OwnedFd>>,
+    exit_status: watch::Receiver<Option<ExitStatus>>,
+}
+
+impl Child {
+    /// `pid` has to be a child of this process
+    pub fn reap(pid: pid_t) -> std::io::Result<Self> {
+        // the pidfd is owned by the AsyncFd, so it stays open until the AsyncFd is dropped
+        let pidfd = Arc::new(unsafe {
+            AsyncFd::register_with_interest(pidfd_open(pid)?, Interest::READABLE)?
+        });
+        let (sender, exit_status) = watch::channel(None);
+
+        let reaper_pidfd = pidfd.clone();
+        tokio::spawn(async move {
+            // readable once the child exits
+            let _ = reaper_pidfd.readable().await;
+            let mut status = 0;
+            let result = unsafe { libc::waitpid(pid, &mut status, 0) };
+            let exit_status = if result == pid {
+                ExitStatus::from_raw(status)
+            } else {
+                ExitStatus::Lost
+            };
+            let _ = sender.send(Some(exit_status));
+        });
+
+        Ok(Self {
+            pid,
+            pidfd,
+            exit_status,
+        })
+    }
+
+    /// `signal` to the child itself, never to another process reusing its pid
+    /// Nothing happens once the child exited.
+    pub fn send_signal(&self, signal: libc::c_int) {
+        unsafe {
+            libc::syscall(
+                libc::SYS_pidfd_send_signal,
+                self.pidfd.as_raw_fd(),
+                signal,
+                std::ptr::null::<libc::siginfo_t>(),
+                0,
+            );
+        }
+    }
+
+    /// None while the child is alive
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        *self.exit_status.borrow()
+    }
+
+    pub async fn wait(&self) -> ExitStatus {
+        let mut exit_status = self.exit_status.clone();
+        let result = match exit_status.wait_for(Option::is_some).await {
+            Ok(exit_status) => exit_status.unwrap_or(ExitStatus::Lost),
+            // the reaper task is gone
+            Err(_) => ExitStatus::Lost,
+        };
+        result
+    }
+
+    /// None on timeout
+    pub async fn wait_timeout(&self, timeout: Duration) -> Option<ExitStatus> {
+        tokio::time::timeout(timeout, self.wait()).await.ok()
+    }
+}
+
+fn pidfd_open(pid: pid_t) -> std::io::Result<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
 pub struct Process {
-    pub execution_manifest: Arc<Mutex<ExecutionManifest>>,
+    pub execution_manifest: ExecutionManifest,
     pub process_state: ProcessState,
     pub pid: Option<pid_t>,
+    pub child: Option<Child>,
+    /// of the last run
+    pub exit_status: Option<ExitStatus>,
+    pub started_at: Option<Instant>,
+    /// restarts since the process was launched by a transition
+    pub restart_count: u32,
+    /// launched by a transition whose state has a process depending on it as Terminated, its
+    /// exit is checked by the transition instead of being supervised
+    pub one_shot: bool,
+}
+
+// process name / process
+pub type ProcessHashMap = HashMap<String, Process>;
+
+impl Process {
+    pub fn new(execution_manifest: ExecutionManifest) -> Self {
+        Self {
+            execution_manifest,
+            process_state: ProcessState::Idle,
+            pid: None,
+            child: None,
+            exit_status: None,
+            started_at: None,
+            restart_count: 0,
+            one_shot: false,
+        }
+    }
+
+    /// Build the command to launch the executable of this process
+    ///
+    /// `argument` is passed as `--KEY=VALUE` (or `--KEY` for an empty value) sorted by key,
+    /// and `environmental_variable` overrides the machine's `environment_variable`.
+    pub fn command<P: AsRef<Path>>(
+        &self,
+        executable: P,
+        machine_manifest: &MachineManifest,
+    ) -> Command {
+        let mut command = Command::new(executable.as_ref());
+
+        let arguments: BTreeMap<_, _> = self.execution_manifest.argument.iter().collect();
+        for (key, value) in arguments {
+            if value.is_empty() {
+                command.arg(format!("--{}", key));
+            } else {
+                command.arg(format!("--{}={}", key, value));
+            }
+        }
+
+        let mut environment = machine_manifest.environment_variable.clone();
+        environment.extend(self.execution_manifest.environmental_variable.clone());
+        command.envs(environment);
+
+        // own process group to kill the process and its children at once
+        command.process_group(0);
+
+        command
+    }
+
+    /// fork/exec the executable, Idle -> Starting
+    /// The child joins `cgroup`, then the rlimits, scheduling, user, groups and capabilities of
+    /// the manifest are applied before exec.
+    /// stdout and stderr of the child are returned to be captured.
+    pub fn start<P: AsRef<Path>>(
+        &mut self,
+        executable: P,
+        machine_manifest: &MachineManifest,
+        cgroup: Option<&Path>,
+    ) -> Result<Pipes> {
+        let launch_failed = |error: String| {
+            ApplicationError::LaunchFailed(self.execution_manifest.name.clone(), error)
+        };
+
+        let mut command = self.command(executable, machine_manifest);
+        // before the privileges are dropped
+        if let Some(cgroup) = cgroup {
+            resource_group::join(&mut command, cgroup)
+                .map_err(|error| launch_failed(error.to_string()))?;
+        }
+        set_rlimits(&mut command, &self.execution_manifest.rlimit);
+        set_scheduling(&mut command, &self.execution_manifest);
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let credential = Credential::resolve(&self.execution_manifest).map_err(launch_failed)?;
+        if let Some(credential) = credential {
+            credential.apply(&mut command);
+        }
+        let mut child = command
+            .spawn()
+            .map_err(|error| launch_failed(error.to_string()))?;
+        let pipes = Pipes {
+            stdout: child.stdout.take().unwrap(),
+            stderr: child.stderr.take().unwrap(),
+        };
+
+        // the child is reaped through its pidfd, not by `std::process::Child`
+        let pid = child.id() as pid_t;
+        let child = Child::reap(pid).map_err(|error| {
+            unsafe {
+                libc::kill(pid, libc::SIGKILL);
+            }
+            ApplicationError::LaunchFailed(self.execution_manifest.name.clone(), error.to_string())
+        })?;
+        self.pid = Some(pid);
+        self.child = Some(child);
+        self.exit_status = None;
+        self.started_at = Some(Instant::now());
+        self.process_state = ProcessState::Starting;
+        Ok(pipes)
+    }
+
+    /// `enter_exit_timeout.enter`, or machine's `default_application_timeout`
+    pub fn enter_timeout(&self, machine_manifest: &MachineManifest) -> Duration {
+        self.timeout(machine_manifest, |timeout| timeout.enter)
+    }
+
+    /// `enter_exit_timeout.exit`, or machine's `default_application_timeout`
+    pub fn exit_timeout(&self, machine_manifest: &MachineManifest) -> Duration {
+        self.timeout(machine_manifest, |timeout| timeout.exit)
+    }
+
+    fn timeout<F>(&self, machine_manifest: &MachineManifest, select: F) -> Duration
+    where
+        F: Fn(&EnterExitTimeout) -> i32,
+    {
+        let seconds = match &self.execution_manifest.enter_exit_timeout {
+            Some(timeout) => select(timeout).max(0) as u64,
+            None => machine_manifest
+                .default_application_timeout
+                .map(u64::from)
+                .unwrap_or(DEFAULT_APPLICATION_TIMEOUT),
+        };
+        Duration::from_secs(seconds)
+    }
+
+    /// delay before the `attempt`th restart, 1-based
+    pub fn restart_backoff(&self, attempt: u32) -> Duration {
+        let backoff = Duration::from_millis(self.execution_manifest.restart_backoff.into());
+        backoff.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+    }
+
+    /// reported to SM if the process terminates unexpectedly
+    pub fn execution_error(&self) -> ExecutionError {
+        if self.exit_status == Some(ExitStatus::OomKilled) {
+            return OOM_EXECUTION_ERROR;
+        }
+        self.execution_manifest
+            .execution_error
+            .unwrap_or(DEFAULT_EXECUTION_ERROR)
+    }
+
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self.process_state,
+            ProcessState::Starting | ProcessState::Running
+        )
+    }
+}
+
+fn set_rlimits(command: &mut Command, rlimits: &HashMap<RlimitResource, Rlimit>) {
+    if rlimits.is_empty() {
+        return;
+    }
+
+    let rlimits: Vec<_> = rlimits
+        .iter()
+        .map(|(resource, rlimit)| {
+            let resource = match resource {
+                RlimitResource::As => libc::RLIMIT_AS,
+                RlimitResource::Core => libc::RLIMIT_CORE,
+                RlimitResource::Cpu => libc::RLIMIT_CPU,
+                RlimitResource::Data => libc::RLIMIT_DATA,
+                RlimitResource::Fsize => libc::RLIMIT_FSIZE,
+                RlimitResource::Memlock => libc::RLIMIT_MEMLOCK,
+                RlimitResource::Nofile => libc::RLIMIT_NOFILE,
+                RlimitResource::Nproc => libc::RLIMIT_NPROC,
+                RlimitResource::Stack => libc::RLIMIT_STACK,
+            };
+            let rlimit = libc::rlimit {
+                rlim_cur: rlimit.soft,
+                rlim_max: rlimit.hard,
+            };
+            (resource, rlimit)
+        })
+        .collect();
+    unsafe {
+        command.pre_exec(move || {
+            for (resource, rlimit) in &rlimits {
+                if libc::setrlimit(*resource, rlimit) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+/// real-time policies need CAP_SYS_NICE
+fn set_scheduling(command: &mut Command, execution_manifest: &ExecutionManifest) {
+    let policy = execution_manifest.scheduling_policy.map(|policy| {
+        let priority = execution_manifest
+            .scheduling_priority
+            .unwrap_or(*policy.priority_range().start());
+        let policy = match policy {
+            SchedulingPolicy::Other => libc::SCHED_OTHER,
+            SchedulingPolicy::Fifo => libc::SCHED_FIFO,
+            SchedulingPolicy::Rr => libc::SCHED_RR,
+        };
+        (policy, libc::sched_param { sched_priority: priority })
+    });
+    let cpu_set = execution_manifest.cpu_affinity.map(|mask| {
+        let mut cpu_set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        for cpu in (0..u64::BITS as usize).filter(|cpu| mask & (1 << cpu) != 0) {
+            unsafe { libc::CPU_SET(cpu, &mut cpu_set) };
+        }
+        cpu_set
+    });
+    if policy.is_none() && cpu_set.is_none() {
+        return;
+    }
+
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(cpu_set) = &cpu_set {
+                let size = std::mem::size_of::<libc::cpu_set_t>();
+                if libc::sched_setaffinity(0, size, cpu_set) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if let Some((policy, param)) = &policy {
+                if libc::sched_setscheduler(0, *policy, param) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+/// SIGTERM, then SIGKILL if the child doesn't exit within `timeout`
+/// returns false if the child had to be killed
+pub async fn terminate(child: &Child, timeout: Duration) -> bool {
+    // already reaped
+    if child.exit_status().is_some() {
+        return true;
+    }
+    child.send_signal(libc::SIGTERM);
+    if child.wait_timeout(timeout).await.is_some() {
+        return true;
+    }
+
+    println!("{} doesn't exit within {:?}, kill it", child.pid, timeout);
+    kill(child).await;
+    false
+}
+
+/// SIGKILL to the process and its process group
+pub async fn kill(child: &Child) {
+    // the process group id is not reused while its leader isn't reaped
+    if child.exit_status().is_none() {
+        unsafe {
+            libc::kill(-child.pid, libc::SIGKILL);
+        }
+        child.send_signal(libc::SIGKILL);
+    }
+    // SIGKILL can't be ignored
+    child.wait().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    #[test]
+    fn command() {
+        let execution_manifest = ExecutionManifest::from(
+            r#"
+            name: APP
+            environmental_variable:
+              ENV1: "application"
+              ENV3: "application only"
+            argument:
+              ARG2: "argument variable2"
+              ARG1: "argument variable1"
+              ARG3: ""
+        "#,
+        )
+        .unwrap();
+        let machine_manifest = MachineManifest::from(
+            r#"
+            environment_variable:
+              ENV1: "machine"
+              ENV2: "machine only"
+        "#,
+        )
+        .unwrap();
+
+        let process = Process::new(execution_manifest);
+        let command = process.command("/usr/bin/oara/APP", &machine_manifest);
+
+        assert_eq!(command.get_program(), "/usr/bin/oara/APP");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            vec![
+                "--ARG1=argument variable1",
+                "--ARG2=argument variable2",
+                "--ARG3"
+            ],
+        );
+
+        let environment: HashMap<_, _> = command
+            .get_envs()
+            .map(|(key, value)| (key.to_owned(), value.unwrap().to_owned()))
+            .collect();
+        assert_eq!(environment.len(), 3);
+        assert_eq!(environment.get(std::ffi::OsStr::new("ENV1")).unwrap(), "application");
+        assert_eq!(environment.get(std::ffi::OsStr::new("ENV2")).unwrap(), "machine only");
+        assert_eq!(
+            environment.get(std::ffi::OsStr::new("ENV3")).unwrap(),
+            "application only"
+        );
+    }
+
+    #[test]
+    fn start_failure() {
+        let execution_manifest = ExecutionManifest::from("name: APP").unwrap();
+        let machine_manifest = MachineManifest::from("").unwrap();
+
+        let mut process = Process::new(execution_manifest);
+        let result = process.start("/not/existing/APP", &machine_manifest, None);
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("Failed to launch APP : "));
+        assert_eq!(process.process_state, ProcessState::Idle);
+        assert!(process.pid.is_none());
+    }
+
+    #[tokio::test]
+    async fn start_rlimit() {
+        let execution_manifest = ExecutionManifest::from(
+            r#"
+            name: APP
+            rlimit:
+              NOFILE:
+                soft: 64
+                hard: 128
+            "#,
+        )
+        .unwrap();
+        let machine_manifest = MachineManifest::from("").unwrap();
+        let output = std::env::temp_dir().join("application-rlimit.out");
+        let executable = std::env::temp_dir().join("application-rlimit.sh");
+        std::fs::write(
+            &executable,
+            format!("#!/bin/sh\necho $(ulimit -Sn) $(ulimit -Hn) > {}", output.display()),
+        )
+        .unwrap();
+        std::fs::set_permissions(&executable, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut process = Process::new(execution_manifest);
+        process.start(&executable, &machine_manifest, None).unwrap();
+        let child = process.child.clone().unwrap();
+        assert_eq!(child.wait().await, ExitStatus::Exited(0));
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "64 128\n");
+
+        std::fs::remove_file(&executable).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    /// executable writing nice, rt_priority and policy of stat(5), then the allowed cpus
+    fn install_scheduling_script(name: &str) -> (PathBuf, PathBuf) {
+        let output = std::env::temp_dir().join(format!("application-{}.out", name));
+        let executable = std::env::temp_dir().join(format!("application-{}.sh", name));
+        std::fs::write(
+            &executable,
+            format!(
+                "#!/bin/sh\n(cut -d' ' -f19,40,41 /proc/$$/stat; grep Cpus_allowed_list /proc/$$/status | cut -f2) > {}",
+                output.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&executable, std::fs::Permissions::from_mode(0o755)).unwrap();
+        (executable, output)
+    }
+
+    #[tokio::test]
+    async fn start_scheduling_other() {
+        let execution_manifest = ExecutionManifest::from(
+            r#"
+            name: APP
+            scheduling_policy: OTHER
+            cpu_affinity: 0x1
+            "#,
+        )
+        .unwrap();
+        let machine_manifest = MachineManifest::from("").unwrap();
+        let (executable, output) = install_scheduling_script("scheduling-other");
+
+        let mut process = Process::new(execution_manifest);
+        process.start(&executable, &machine_manifest, None).unwrap();
+        let child = process.child.clone().unwrap();
+        assert_eq!(child.wait().await, ExitStatus::Exited(0));
+        // the nice value is inherited from EM
+        let nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            format!("{} 0 0\n0\n", nice)
+        );
+
+        std::fs::remove_file(&output).unwrap();
+        std::fs::remove_file(&executable).unwrap();
+    }
+
+    #[tokio::test]
+    async fn start_scheduling_fifo() {
+        // SCHED_FIFO needs CAP_SYS_NICE
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let execution_manifest = ExecutionManifest::from(
+            r#"
+            name: APP
+            scheduling_policy: FIFO
+            scheduling_priority: 10
+            cpu_affinity: 0x1
+            "#,
+        )
+        .unwrap();
+        let machine_manifest = MachineManifest::from("").unwrap();
+        let (executable, output) = install_scheduling_script("scheduling-fifo");
+
+        let mut process = Process::new(execution_manifest);
+        process.start(&executable, &machine_manifest, None).unwrap();
+        let child = process.child.clone().unwrap();
+        assert_eq!(child.wait().await, ExitStatus::Exited(0));
+        let nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            format!("{} 10 1\n0\n", nice)
+        );
+
+        std::fs::remove_file(&output).unwrap();
+        std::fs::remove_file(&executable).unwrap();
+    }
+
+    #[test]
+    fn enter_exit_timeout() {
+        let machine_manifest = MachineManifest::from("").unwrap();
+        let mut process = Process::new(ExecutionManifest::from("name: APP").unwrap());
+        assert_eq!(
+            process.exit_timeout(&machine_manifest),
+            Duration::from_secs(DEFAULT_APPLICATION_TIMEOUT)
+        );
+
+        let machine_manifest = MachineManifest::from("default_application_timeout: 5").unwrap();
+        assert_eq!(
+            process.exit_timeout(&machine_manifest),
+            Duration::from_secs(5)
+        );
+
+        assert_eq!(
+            process.enter_timeout(&machine_manifest),
+            Duration::from_secs(5)
+        );
+
+        process.execution_manifest.enter_exit_timeout =
+            Some(EnterExitTimeout { enter: 1, exit: 2 });
+        assert_eq!(
+            process.enter_timeout(&machine_manifest),
+            Duration::from_secs(1)
+        );
+        assert_eq!(
+            process.exit_timeout(&machine_manifest),
+            Duration::from_secs(2)
+        );
+    }
+
+    #[tokio::test]
+    async fn terminate_graceful() {
+        let pid = Command::new("sleep").arg("10").spawn().unwrap().id() as pid_t;
+        let child = Child::reap(pid).unwrap();
+        assert!(terminate(&child, Duration::from_secs(1)).await);
+        assert_eq!(child.exit_status(), Some(ExitStatus::Signaled(libc::SIGTERM)));
+    }
+
+    #[tokio::test]
+    async fn terminate_exited() {
+        let pid = Command::new("true").spawn().unwrap().id() as pid_t;
+        let child = Child::reap(pid).unwrap();
+        assert_eq!(child.wait().await, ExitStatus::Exited(0));
+        // the pid may belong to another process by now, nothing is signaled
+        assert!(terminate(&child, Duration::from_secs(1)).await);
+        kill(&child).await;
+        assert_eq!(child.exit_status(), Some(ExitStatus::Exited(0)));
+    }
+
+    #[tokio::test]
+    async fn exit_status() {
+        let pid = Command::new("sh")
+            .arg("-c")
+            .arg("exit 3")
+            .spawn()
+            .unwrap()
+            .id() as pid_t;
+        let child = Child::reap(pid).unwrap();
+        assert_eq!(child.wait().await, ExitStatus::Exited(3));
+    }
+
+    #[tokio::test]
+    async fn terminate_kill() {
+        let pid = Command::new("sh")
+            .arg("-c")
+            .arg("trap '' TERM; while true; do sleep 1; done")
+            .spawn()
+            .unwrap()
+            .id() as pid_t;
+        // give the shell time to install the trap
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let child = Child::reap(pid).unwrap();
+        assert!(!terminate(&child, Duration::from_millis(100)).await);
+        assert_eq!(child.exit_status(), Some(ExitStatus::Signaled(libc::SIGKILL)));
+    }
 }
@@ -0,0 +1,252 @@
+use ara_exec::manifest::credential::capability_number;
+use ara_exec::manifest::execution_manifest::ExecutionManifest;
+use libc::{gid_t, uid_t};
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+#[repr(C)]
+struct CapUserHeader {
+    version: u32,
+    pid: libc::c_int,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapUserData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// user, groups and capabilities of a process
+/// Names are resolved before fork since getpwnam() and co. are not async-signal-safe.
+#[derive(Debug, Default, PartialEq)]
+pub struct Credential {
+    uid: Option<uid_t>,
+    gid: Option<gid_t>,
+    groups: Option<Vec<gid_t>>,
+    bounding: Option<Vec<u32>>,
+    ambient: Vec<u32>,
+}
+
+impl Credential {
+    /// None if the process runs with EM's credential
+    pub fn resolve(execution_manifest: &ExecutionManifest) -> Result<Option<Self>, String> {
+        let manifest = execution_manifest;
+        if manifest.user.is_none()
+            && manifest.group.is_none()
+            && manifest.supplementary_groups.is_empty()
+            && manifest.capability.is_none()
+        {
+            return Ok(None);
+        }
+
+        let mut credential = Credential::default();
+        let mut primary_gid = None;
+        if let Some(user) = &manifest.user {
+            let (uid, gid) = user
+                .resolve_user()
+                .ok_or_else(|| format!("unknown user {}", user))?;
+            credential.uid = Some(uid);
+            primary_gid = gid;
+        }
+        if let Some(group) = &manifest.group {
+            primary_gid = Some(
+                group
+                    .resolve_group()
+                    .ok_or_else(|| format!("unknown group {}", group))?,
+            );
+        }
+        credential.gid = primary_gid;
+
+        // the supplementary groups of EM are not inherited by another user
+        if !manifest.supplementary_groups.is_empty() || manifest.user.is_some() {
+            let groups = manifest
+                .supplementary_groups
+                .iter()
+                .map(|group| {
+                    group
+                        .resolve_group()
+                        .ok_or_else(|| format!("unknown group {}", group))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            credential.groups = Some(groups);
+        }
+
+        if let Some(capability) = &manifest.capability {
+            let numbers = |names: &[String]| {
+                names
+                    .iter()
+                    .map(|name| {
+                        capability_number(name)
+                            .ok_or_else(|| format!("unknown capability {}", name))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            };
+            credential.bounding = capability.bounding.as_deref().map(numbers).transpose()?;
+            credential.ambient = numbers(&capability.ambient)?;
+        }
+
+        Ok(Some(credential))
+    }
+
+    /// applied in the child between fork and exec
+    pub fn apply(self, command: &mut Command) {
+        unsafe {
+            command.pre_exec(move || self.apply_in_child());
+        }
+    }
+
+    /// only async-signal-safe calls
+    fn apply_in_child(&self) -> io::Result<()> {
+        // the permitted set survives setuid() for the ambient capabilities
+        if !self.ambient.is_empty() {
+            check(unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 1, 0, 0, 0) })?;
+        }
+
+        // needs CAP_SETPCAP, before dropping the privileges
+        if let Some(bounding) = &self.bounding {
+            for capability in 0..64 {
+                if bounding.contains(&capability) {
+                    continue;
+                }
+                if unsafe { libc::prctl(libc::PR_CAPBSET_DROP, capability, 0, 0, 0) } < 0 {
+                    let error = io::Error::last_os_error();
+                    // beyond the last capability of the kernel
+                    if error.raw_os_error() == Some(libc::EINVAL) {
+                        break;
+                    }
+                    return Err(error);
+                }
+            }
+        }
+
+        if let Some(groups) = &self.groups {
+            check(unsafe { libc::setgroups(groups.len(), groups.as_ptr()) })?;
+        }
+        if let Some(gid) = self.gid {
+            check(unsafe { libc::setgid(gid) })?;
+        }
+        if let Some(uid) = self.uid {
+            check(unsafe { libc::setuid(uid) })?;
+        }
+
+        if !self.ambient.is_empty() {
+            // an ambient capability has to be permitted and inheritable
+            let mut data = [CapUserData::default(); 2];
+            for &capability in &self.ambient {
+                let set = &mut data[(capability / 32) as usize];
+                let bit = 1 << (capability % 32);
+                set.effective |= bit;
+                set.permitted |= bit;
+                set.inheritable |= bit;
+            }
+            let mut header = CapUserHeader {
+                version: LINUX_CAPABILITY_VERSION_3,
+                pid: 0,
+            };
+            let result =
+                unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } as i32;
+            check(result)?;
+
+            for &capability in &self.ambient {
+                check(unsafe {
+                    libc::prctl(
+                        libc::PR_CAP_AMBIENT,
+                        libc::PR_CAP_AMBIENT_RAISE,
+                        capability,
+                        0,
+                        0,
+                    )
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn check(result: libc::c_int) -> io::Result<()> {
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        let manifest = ExecutionManifest::from("name: APP").unwrap();
+        assert_eq!(Credential::resolve(&manifest).unwrap(), None);
+
+        let manifest = ExecutionManifest::from(
+            r#"
+            name: APP
+            user: root
+            capability:
+              bounding:
+                - CAP_KILL
+                - CAP_NET_BIND_SERVICE
+              ambient:
+                - net_bind_service
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            Credential::resolve(&manifest).unwrap(),
+            Some(Credential {
+                uid: Some(0),
+                gid: Some(0),
+                groups: Some(vec![]),
+                bounding: Some(vec![5, 10]),
+                ambient: vec![10],
+            })
+        );
+
+        let manifest = ExecutionManifest::from("name: APP\ngroup: no-such-group").unwrap();
+        assert!(Credential::resolve(&manifest).is_err());
+    }
+
+    #[test]
+    fn apply() {
+        // switching the user needs root
+        if unsafe { libc::geteuid() } != 0 {
+            return;
+        }
+
+        let manifest = ExecutionManifest::from(
+            r#"
+            name: APP
+            user: 65534
+            group: 65534
+            capability:
+              bounding:
+                - CAP_NET_BIND_SERVICE
+              ambient:
+                - CAP_NET_BIND_SERVICE
+            "#,
+        )
+        .unwrap();
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(
+            "id -u; id -g; id -G; grep -E '^Cap(Amb|Bnd)' /proc/self/status | cut -f2",
+        );
+        Credential::resolve(&manifest)
+            .unwrap()
+            .unwrap()
+            .apply(&mut command);
+
+        let output = command.output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "65534\n65534\n65534\n0000000000000400\n0000000000000400\n"
+        );
+    }
+}
@@ -5,3 +5,6 @@ pub mod configuration;
 pub const MACHINE_MANIFEST_FILE: &str = "machine_manifest.yaml";
 pub const EXECUTION_MANIFEST_FILE: &str = "execution_manifest.yaml";
 pub const OARA_CONFIG_EXEC: &str = "exec";
+pub const OARA_APP_BIN: &str = "bin";
+pub const OARA_APP_MANIFEST: &str = "manifest";
+pub const APP_EXECUTION_MANIFEST_SUFFIX: &str = "_em_manifest.yaml";
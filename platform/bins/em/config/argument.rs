@@ -1,9 +1,12 @@
+use crate::resource_group::DEFAULT_CGROUP_ROOT;
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use std::num::NonZeroUsize;
 use std::path::Path;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 enum ArgumentError {
     #[error("Invalid RO OARA Root: {0}")]
     InvalidROOARARoot(String),
@@ -13,6 +16,17 @@ enum ArgumentError {
     InvalidOARAConfig(String),
 }
 
+/// How the machine is powered off or rebooted by MachineFG Shutdown/Restart
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MachineActionKind {
+    /// reboot(2)
+    Reboot,
+    /// systemctl poweroff/reboot
+    Systemd,
+    /// only logged, e.g. in containers
+    None,
+}
+
 #[derive(Parser, Debug)]
 #[command(name = "EM", version = "1.0", about = "Execution management")]
 pub struct EMArgument {
@@ -22,6 +36,12 @@ pub struct EMArgument {
     pub rw_oara_root: String,
     #[arg(short, long, default_value = "/etc/oara", help = "configuration path")]
     pub config: String,
+    #[arg(long, value_enum, default_value_t = MachineActionKind::Reboot, help = "machine action backend")]
+    pub machine_action: MachineActionKind,
+    #[arg(long, default_value = DEFAULT_CGROUP_ROOT, help = "cgroup v2 root of resource groups")]
+    pub cgroup_root: String,
+    #[arg(long, help = "maximum number of processes starting at once [default: unlimited]")]
+    pub max_parallel_start: Option<NonZeroUsize>,
 }
 
 pub fn parse() -> Result<EMArgument> {
@@ -7,11 +7,12 @@ use std::{
 use anyhow::Result;
 use ara_exec::manifest::execution_manifest::ExecutionManifest;
 use ara_exec::manifest::machine_manifest::MachineManifest;
+use std::os::unix::fs::PermissionsExt;
 use thiserror::Error;
 
 /// Load manifest files
 /// Structures
-/// /usr/bin/oara  (RO_OARA_ROOT)
+/// /usr/bin/oara  (RO_OARA_ROOT, executables of /etc/oara/exec)
 ///           |- EM
 ///           |- SM
 ///           ...
@@ -25,22 +26,25 @@ use thiserror::Error;
 ///               \- others_execution_manifest.yaml
 /// /opt/oara (RW_OARA_ROOT, optional)
 ///           |- App1
-///           |   |- bin - App1
-///           |   \- manifest - execution_manifest.yaml
+///           |   |- bin - App1 (executables of App1/manifest)
+///           |   \- manifest - execution_manifest.yaml or app1_em_manifest.yaml
 ///           |- others
 ///           ...
 
 #[derive(Debug, Error)]
 enum ExecutionManifestError {
-    #[error("Duplicated application name : {0}")]
-    DuplicatedAppName(String),
+    #[error("Duplicated application name : {0} in {1} and {2}")]
+    DuplicatedAppName(String, String, String),
     #[error("Missing dependency application : {0} for {1}")]
     MissingDependencyApp(String, String),
     #[error("Self dependency is not allowed : {0}")]
-    #[allow(unused)]    // FIXME
     SelfDependency(String),
     #[error("Dependency app({0}) is not in the mode")]
     InvalidModeDependency(String),
+    #[error("Executable({0}) doesn't exist for {1}")]
+    MissingExecutable(String, String),
+    #[error("Executable({0}) is not executable for {1}")]
+    NotExecutable(String, String),
     // circular dependency
 }
 
@@ -50,14 +54,49 @@ pub fn load_machine_manifest<P: AsRef<Path>>(path: P) -> Result<MachineManifest>
     MachineManifest::from_file(machine_manifest_path)
 }
 
+/// `executable` relative to `root`, `name` if not given
+fn resolve_executable(execution_manifest: &mut ExecutionManifest, root: &Path) {
+    let executable = execution_manifest
+        .executable
+        .as_deref()
+        .unwrap_or(&execution_manifest.name);
+    execution_manifest.executable = Some(root.join(executable).to_string_lossy().into_owned());
+}
+
+/// execution manifest of an application folder in RW_OARA_ROOT, see above
+/// `execution_manifest.yaml` is preferred to `<app>_em_manifest.yaml`, <app> in lower case.
+fn find_app_manifest(app_path: &Path) -> Option<PathBuf> {
+    let manifest_path = app_path.join(super::OARA_APP_MANIFEST);
+    let execution_manifest = manifest_path.join(super::EXECUTION_MANIFEST_FILE);
+    if execution_manifest.is_file() {
+        return Some(execution_manifest);
+    }
+
+    let app_name = app_path.file_name()?.to_string_lossy().to_lowercase();
+    let execution_manifest = manifest_path.join(format!(
+        "{}{}",
+        app_name,
+        super::APP_EXECUTION_MANIFEST_SUFFIX
+    ));
+    if execution_manifest.is_file() {
+        return Some(execution_manifest);
+    }
+
+    None
+}
+
 /// Load execution manifest files
-pub fn load_execution_manifest<P1, P2>(
+/// A folder in RW_OARA_ROOT without an execution manifest is not an application, and skipped.
+/// `executable` of every manifest is resolved against the root of the place it's found, see above
+pub fn load_execution_manifest<P1, P2, P3>(
     oara_config_path: P1,
-    rw_oara_path: P2,
+    ro_oara_path: P2,
+    rw_oara_path: P3,
 ) -> Result<Vec<ExecutionManifest>>
 where
     P1: AsRef<Path>,
     P2: AsRef<Path>,
+    P3: AsRef<Path>,
 {
     let mut exec_path = PathBuf::from(oara_config_path.as_ref());
     exec_path.push(super::OARA_CONFIG_EXEC);
@@ -68,7 +107,7 @@ where
         let path = entry.path();
 
         if path.extension().map(|ext| ext == "yaml").unwrap_or(false) {
-            execution_manifest_files.push(path);
+            execution_manifest_files.push((path, ro_oara_path.as_ref().to_path_buf()));
         }
     }
 
@@ -77,32 +116,79 @@ where
             let entry = entry?;
             let path = entry.path();
 
-            if path.is_dir() {
-                execution_manifest_files
-                    .push(path.join("mainfest").join(super::EXECUTION_MANIFEST_FILE));
+            if !path.is_dir() {
+                continue;
+            }
+            match find_app_manifest(&path) {
+                Some(manifest_path) => {
+                    execution_manifest_files.push((manifest_path, path.join(super::OARA_APP_BIN)))
+                }
+                None => println!("No execution manifest in {}, skip it", path.display()),
             }
         }
     }
 
     let mut execution_manifests = Vec::new();
-    for path in execution_manifest_files {
-        execution_manifests.push(ExecutionManifest::from_file(path)?);
+    for (path, root) in execution_manifest_files {
+        let mut execution_manifest = ExecutionManifest::from_file(&path)
+            .map_err(|error| error.context(format!("Failed to load {}", path.display())))?;
+        resolve_executable(&mut execution_manifest, &root);
+        execution_manifests.push(execution_manifest);
     }
 
     Ok(execution_manifests)
 }
 
+/// every executable has to be an executable file, not to fail at spawn time
+pub fn validate_executable(executions: &[ExecutionManifest]) -> Result<()> {
+    for execution in executions {
+        let executable = execution
+            .executable
+            .clone()
+            .unwrap_or_else(|| execution.name.clone());
+        let metadata = match std::fs::metadata(&executable) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                return Err(
+                    ExecutionManifestError::MissingExecutable(executable, execution.name.clone())
+                        .into(),
+                )
+            }
+        };
+        if !metadata.is_file() || metadata.permissions().mode() & 0o111 == 0 {
+            return Err(
+                ExecutionManifestError::NotExecutable(executable, execution.name.clone()).into(),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn source(execution: &ExecutionManifest) -> String {
+    execution
+        .source
+        .as_ref()
+        .map(|source| source.display().to_string())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
 pub fn validate_manifest(
     machine: &MachineManifest,
     executions: &Vec<ExecutionManifest>,
 ) -> Result<()> {
-    let mut app_hashmap = HashMap::new();
+    let mut app_hashmap: HashMap<&str, &ExecutionManifest> = HashMap::new();
 
     // validate app and mode dependency
     for execution in executions {
-        let _ = execution.validate(&machine)?;
-        if app_hashmap.contains_key(execution.name.as_str()) {
-            return Err(ExecutionManifestError::DuplicatedAppName(execution.name.clone()).into());
+        execution.validate(machine)?;
+        if let Some(duplicated) = app_hashmap.get(execution.name.as_str()) {
+            return Err(ExecutionManifestError::DuplicatedAppName(
+                execution.name.clone(),
+                source(duplicated),
+                source(execution),
+            )
+            .into());
         } else {
             app_hashmap.insert(execution.name.as_str(), execution);
         }
@@ -113,7 +199,12 @@ pub fn validate_manifest(
     // dependency application should be configured
     for execution in executions {
         for app_dependency in &execution.app_dependency {
-            let (app, _) = app_dependency.split_once(".").unwrap();
+            let app = app_dependency.app.as_str();
+            // Not possible to have self dependency
+            if app == execution.name {
+                return Err(ExecutionManifestError::SelfDependency(app.to_owned()).into());
+            }
+
             if !app_hashmap.contains_key(app) {
                 return Err(ExecutionManifestError::MissingDependencyApp(
                     app.to_owned(),
@@ -122,12 +213,6 @@ pub fn validate_manifest(
                 .into());
             }
 
-            // Not possible to have self dependency
-            // It's not possible yet
-            // if app == execution.name {
-            //     return Err(ExecutionManifestError::SelfDependency(app.to_owned()).into());
-            // }
-
             // dependency app should in the same function group's state
 
             let mut mode_dependency_valid = false;
@@ -144,7 +229,7 @@ pub fn validate_manifest(
         }
     }
 
-    // TBD, circular dependency
+    // circular dependency is checked for every function group state by group()
 
     Ok(())
 }
@@ -306,7 +391,7 @@ mod tests {
         let contents = valid_execution_manifest();
         let oara_exec_path = add_oara_exec_folder(&oara_config_path);
         let _oara_exec_file_path = configure_execution_manifest(oara_exec_path, "t3", contents);
-        let execution_manifest = load_execution_manifest(&oara_config_path, "").unwrap();
+        let execution_manifest = load_execution_manifest(&oara_config_path, "/usr/bin/oara", "").unwrap();
         assert!(validate_manifest(&machine_manifest, &execution_manifest).is_ok());
 
         fs::remove_dir_all(&oara_config_path).unwrap();
@@ -320,7 +405,7 @@ mod tests {
         let _machine_manifest = load_machine_manifest(&oara_config_path).unwrap();
 
         // load execution manifest
-        let execution_manifest = load_execution_manifest(&oara_config_path, "");
+        let execution_manifest = load_execution_manifest(&oara_config_path, "/usr/bin/oara", "");
         assert!(execution_manifest.is_err()); // No oara/exec folder
 
         fs::remove_dir_all(&oara_config_path).unwrap();
@@ -343,13 +428,21 @@ mod tests {
         let _ = new_exec_file_path.pop();
         let new_file_name = format!("t6_{}", EXECUTION_MANIFEST_FILE);
         new_exec_file_path.push(new_file_name.as_str());
-        fs::copy(oara_exec_file_path, new_exec_file_path).unwrap();
+        fs::copy(&oara_exec_file_path, &new_exec_file_path).unwrap();
 
-        let execution_manifest = load_execution_manifest(&oara_config_path, "").unwrap();
+        let execution_manifest = load_execution_manifest(&oara_config_path, "/usr/bin/oara", "").unwrap();
         let validate = validate_manifest(&machine_manifest, &execution_manifest);
-        assert_eq!(
-            validate.err().map(|e| e.to_string()).unwrap(),
-            String::from("Duplicated application name : SM"),
+        // both files are named, in the order they're loaded
+        let error = validate.err().map(|e| e.to_string()).unwrap();
+        let (first, second) = (
+            oara_exec_file_path.display().to_string(),
+            new_exec_file_path.display().to_string(),
+        );
+        assert!(
+            error == format!("Duplicated application name : SM in {} and {}", first, second)
+                || error == format!("Duplicated application name : SM in {} and {}", second, first),
+            "{}",
+            error
         );
 
         fs::remove_dir_all(&oara_config_path).unwrap();
@@ -373,7 +466,7 @@ mod tests {
         let oara_exec_path = add_oara_exec_folder(&oara_config_path);
         let _ = configure_execution_manifest(oara_exec_path, "t7", app_manifest);
 
-        let execution_manifest = load_execution_manifest(&oara_config_path, "").unwrap();
+        let execution_manifest = load_execution_manifest(&oara_config_path, "/usr/bin/oara", "").unwrap();
         let validate = validate_manifest(&machine_manifest, &execution_manifest);
         assert_eq!(
             validate.err().map(|e| e.to_string()).unwrap(),
@@ -384,7 +477,6 @@ mod tests {
     }
 
     #[test]
-    #[ignore = "not implement missing dependency"]
     fn self_dependency_app() {
         // load machine manifest
         let contents = valid_machine_manifest();
@@ -402,7 +494,7 @@ mod tests {
         let oara_exec_path = add_oara_exec_folder(&oara_config_path);
         let _ = configure_execution_manifest(oara_exec_path, "t8", app_manifest);
 
-        let execution_manifest = load_execution_manifest(&oara_config_path, "").unwrap();
+        let execution_manifest = load_execution_manifest(&oara_config_path, "/usr/bin/oara", "").unwrap();
         let validate = validate_manifest(&machine_manifest, &execution_manifest);
         assert_eq!(
             validate.err().map(|e| e.to_string()).unwrap(),
@@ -437,7 +529,7 @@ mod tests {
         let _ = configure_execution_manifest(&oara_exec_path, "t9", app_manifest1);
         let _ = configure_execution_manifest(&oara_exec_path, "t10", app_manifest2);
 
-        let execution_manifest = load_execution_manifest(&oara_config_path, "").unwrap();
+        let execution_manifest = load_execution_manifest(&oara_config_path, "/usr/bin/oara", "").unwrap();
         let validate = validate_manifest(&machine_manifest, &execution_manifest);
         assert_eq!(
             validate.err().map(|e| e.to_string()).unwrap(),
@@ -449,4 +541,92 @@ mod tests {
 
     #[test]
     fn circular_dependency_app() {}
+
+    #[test]
+    fn rw_oara_root() {
+        let contents = valid_machine_manifest();
+        let oara_config_path = configure_machine_manifest("configuration-t12", contents);
+        let _ = add_oara_exec_folder(&oara_config_path);
+        let rw_oara_path = oara_config_path.join("rw");
+
+        // App1/manifest/app1_em_manifest.yaml, as in README
+        let app1_manifest = rw_oara_path.join("App1").join("manifest");
+        fs::create_dir_all(&app1_manifest).unwrap();
+        let app1_manifest = app1_manifest.join("app1_em_manifest.yaml");
+        fs::write(&app1_manifest, "name: APP1").unwrap();
+
+        // App2/manifest/execution_manifest.yaml
+        let app2_manifest = rw_oara_path.join("App2").join("manifest");
+        fs::create_dir_all(&app2_manifest).unwrap();
+        let app2_manifest = app2_manifest.join(EXECUTION_MANIFEST_FILE);
+        fs::write(&app2_manifest, "name: APP2\nexecutable: app2").unwrap();
+
+        // not an application, skipped
+        fs::create_dir_all(rw_oara_path.join("lost+found")).unwrap();
+        fs::write(rw_oara_path.join("README"), "").unwrap();
+
+        let mut execution_manifest =
+            load_execution_manifest(&oara_config_path, "/usr/bin/oara", &rw_oara_path).unwrap();
+        execution_manifest.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(execution_manifest.len(), 2);
+
+        let app1 = rw_oara_path.join("App1").join("bin").join("APP1");
+        let app2 = rw_oara_path.join("App2").join("bin").join("app2");
+        assert_eq!(execution_manifest[0].executable.as_deref(), app1.to_str());
+        assert_eq!(execution_manifest[0].source.as_ref(), Some(&app1_manifest));
+        assert_eq!(execution_manifest[1].executable.as_deref(), app2.to_str());
+        assert_eq!(execution_manifest[1].source.as_ref(), Some(&app2_manifest));
+
+        fs::remove_dir_all(&oara_config_path).unwrap();
+    }
+
+    #[test]
+    fn executable() {
+        let contents = valid_machine_manifest();
+        let oara_config_path = configure_machine_manifest("configuration-t11", contents);
+        let ro_oara_path = oara_config_path.join("ro");
+        fs::create_dir_all(&ro_oara_path).unwrap();
+
+        let app_manifest1: &'static str = r#"
+            name: SM
+            executable: bin/sm
+        "#;
+        let app_manifest2: &'static str = r#"
+            name: UCM
+        "#;
+        let oara_exec_path = add_oara_exec_folder(&oara_config_path);
+        let _ = configure_execution_manifest(&oara_exec_path, "t11", app_manifest1);
+        let _ = configure_execution_manifest(&oara_exec_path, "t12", app_manifest2);
+
+        let mut execution_manifest =
+            load_execution_manifest(&oara_config_path, &ro_oara_path, "").unwrap();
+        execution_manifest.sort_by(|a, b| a.name.cmp(&b.name));
+        let sm = ro_oara_path.join("bin/sm");
+        let ucm = ro_oara_path.join("UCM");
+        assert_eq!(execution_manifest[0].executable.as_deref(), sm.to_str());
+        assert_eq!(execution_manifest[1].executable.as_deref(), ucm.to_str());
+
+        // MissingExecutable
+        let validate = validate_executable(&execution_manifest);
+        assert_eq!(
+            validate.err().map(|e| e.to_string()).unwrap(),
+            format!("Executable({}) doesn't exist for SM", sm.display()),
+        );
+
+        // NotExecutable
+        fs::create_dir_all(ro_oara_path.join("bin")).unwrap();
+        fs::write(&sm, "").unwrap();
+        fs::write(&ucm, "").unwrap();
+        fs::set_permissions(&ucm, fs::Permissions::from_mode(0o755)).unwrap();
+        let validate = validate_executable(&execution_manifest);
+        assert_eq!(
+            validate.err().map(|e| e.to_string()).unwrap(),
+            format!("Executable({}) is not executable for SM", sm.display()),
+        );
+
+        fs::set_permissions(&sm, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(validate_executable(&execution_manifest).is_ok());
+
+        fs::remove_dir_all(&oara_config_path).unwrap();
+    }
 }
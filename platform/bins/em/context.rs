@@ -0,0 +1,308 @@
+use crate::application::{Process, ProcessHashMap, ProcessState};
+use crate::function_group_state::group::FunctionGroupHashMap;
+use crate::machine_action::{MachineActionBackend, RecordingBackend};
+use crate::resource_group::ResourceGroups;
+use ara_exec::execution_client::ExecutionErrorEvent;
+use ara_exec::function_group::FunctionGroupState;
+use ara_exec::manifest::dependency::ModeDependency;
+use ara_exec::manifest::execution_manifest::ExecutionManifest;
+use ara_exec::manifest::machine_manifest::MachineManifest;
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::futures::Notified;
+use tokio::sync::{broadcast, watch, Mutex, Notify};
+
+// function group / current state
+pub type StateHashMap = HashMap<String, String>;
+
+// function group in the Undefined Function Group State / the error which caused it
+pub type ExecutionErrorHashMap = HashMap<String, ExecutionErrorEvent>;
+
+// pending events of a slow SM connection
+const EXECUTION_ERROR_EVENT_CAPACITY: usize = 16;
+
+/// Why the in-flight SetState of a function group has to stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interruption {
+    /// a newer SetState for the function group arrived
+    Canceled,
+    /// a Running process of the function group terminated by itself
+    UnexpectedTermination,
+}
+
+/// in-flight SetState of a function group
+struct Transition {
+    id: u64,
+    // target state
+    state: String,
+    interrupt: watch::Sender<Option<Interruption>>,
+}
+
+/// Shared resource to manage function group states and processes
+/// It is shared between the main thread and state_receiver, see em.rs
+pub struct Context {
+    pub machine_manifest: MachineManifest,
+    pub fg_hashmap: FunctionGroupHashMap,
+    pub ro_oara_root: PathBuf,
+    pub rw_oara_root: Option<PathBuf>,
+    pub processes: Mutex<ProcessHashMap>,
+    pub states: Mutex<StateHashMap>,
+    pub execution_errors: Mutex<ExecutionErrorHashMap>,
+    process_state_changed: Notify,
+    execution_error_events: broadcast::Sender<ExecutionErrorEvent>,
+    // serialize transitions of the same function group
+    transition_locks: HashMap<String, Mutex<()>>,
+    transitions: std::sync::Mutex<HashMap<String, Transition>>,
+    next_transition_id: AtomicU64,
+    // EM itself goes down, no more transition
+    shutting_down: AtomicBool,
+    machine_action_backend: Arc<dyn MachineActionBackend>,
+    resource_groups: Option<ResourceGroups>,
+    // processes of a transition starting at once
+    max_parallel_start: usize,
+}
+
+impl Context {
+    pub fn new<P: Into<PathBuf>>(
+        machine_manifest: MachineManifest,
+        execution_manifests: &[ExecutionManifest],
+        fg_hashmap: FunctionGroupHashMap,
+        ro_oara_root: P,
+    ) -> Self {
+        let processes = execution_manifests
+            .iter()
+            .map(|manifest| (manifest.name.clone(), Process::new(manifest.clone())))
+            .collect();
+        let states = machine_manifest
+            .function_group_set
+            .iter()
+            .map(|(name, fg)| (name.clone(), fg.initial_mode.clone()))
+            .collect();
+        let transition_locks = machine_manifest
+            .function_group_set
+            .keys()
+            .map(|name| (name.clone(), Mutex::new(())))
+            .collect();
+
+        Self {
+            machine_manifest,
+            fg_hashmap,
+            ro_oara_root: ro_oara_root.into(),
+            rw_oara_root: None,
+            processes: Mutex::new(processes),
+            states: Mutex::new(states),
+            execution_errors: Mutex::new(HashMap::new()),
+            process_state_changed: Notify::new(),
+            execution_error_events: broadcast::channel(EXECUTION_ERROR_EVENT_CAPACITY).0,
+            transition_locks,
+            transitions: std::sync::Mutex::new(HashMap::new()),
+            next_transition_id: AtomicU64::new(0),
+            shutting_down: AtomicBool::new(false),
+            // nothing happens to the machine unless a backend is given
+            machine_action_backend: Arc::new(RecordingBackend::default()),
+            resource_groups: None,
+            max_parallel_start: usize::MAX,
+        }
+    }
+
+    pub fn with_machine_action_backend(mut self, backend: Arc<dyn MachineActionBackend>) -> Self {
+        self.machine_action_backend = backend;
+        self
+    }
+
+    pub fn machine_action_backend(&self) -> &dyn MachineActionBackend {
+        self.machine_action_backend.as_ref()
+    }
+
+    pub fn with_rw_oara_root<P: Into<PathBuf>>(mut self, rw_oara_root: P) -> Self {
+        self.rw_oara_root = Some(rw_oara_root.into());
+        self
+    }
+
+    /// <rw_oara_root>/<name>/log, None without r/w root
+    pub fn log_dir(&self, name: &str) -> Option<PathBuf> {
+        Some(self.rw_oara_root.as_ref()?.join(name).join("log"))
+    }
+
+    pub fn with_resource_groups(mut self, resource_groups: ResourceGroups) -> Self {
+        self.resource_groups = Some(resource_groups);
+        self
+    }
+
+    /// None if the machine manifest has no resource group
+    pub fn resource_groups(&self) -> Option<&ResourceGroups> {
+        self.resource_groups.as_ref()
+    }
+
+    pub fn with_max_parallel_start(mut self, max_parallel_start: NonZeroUsize) -> Self {
+        self.max_parallel_start = max_parallel_start.get();
+        self
+    }
+
+    /// unlimited if not given
+    pub fn max_parallel_start(&self) -> usize {
+        self.max_parallel_start
+    }
+
+    /// cgroup of the process, None if it isn't in a resource group
+    pub fn cgroup(&self, process: &Process) -> Option<PathBuf> {
+        let resource_groups = self.resource_groups.as_ref()?;
+        let resource_group = process.execution_manifest.resource_group.as_ref()?;
+        Some(resource_groups.path(resource_group))
+    }
+
+    /// executable path of the process, resolved by `load_execution_manifest` unless relative
+    pub fn executable(&self, process: &Process) -> PathBuf {
+        let manifest = &process.execution_manifest;
+        self.ro_oara_root
+            .join(manifest.executable.as_deref().unwrap_or(&manifest.name))
+    }
+
+    /// wake up everyone waiting on `wait_process_state`
+    pub fn notify_process_state_changed(&self) {
+        self.process_state_changed.notify_waiters();
+    }
+
+    /// notified by `notify_process_state_changed`
+    /// create it before checking the state not to miss a notification
+    pub fn process_state_changed(&self) -> Notified<'_> {
+        self.process_state_changed.notified()
+    }
+
+    /// wait until the process reaches `state`
+    pub async fn wait_process_state(&self, name: &str, state: ProcessState) {
+        loop {
+            let notified = self.process_state_changed();
+            if self
+                .processes
+                .lock()
+                .await
+                .get(name)
+                .is_some_and(|process| process.process_state == state)
+            {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// register a new transition of the function group, it cancels the in-flight one
+    /// returns the id of the transition and its interruption
+    pub fn begin_transition(
+        &self,
+        fg_state: &FunctionGroupState,
+    ) -> (u64, watch::Receiver<Option<Interruption>>) {
+        let id = self.next_transition_id.fetch_add(1, Ordering::Relaxed);
+        let (interrupt, interrupted) = watch::channel(None);
+        let transition = Transition {
+            id,
+            state: fg_state.function_group_state.clone(),
+            interrupt,
+        };
+
+        let mut transitions = self.transitions.lock().unwrap();
+        if let Some(previous) = transitions.insert(fg_state.function_group.clone(), transition) {
+            println!("cancel the in-flight transition of {}", fg_state.function_group);
+            let _ = previous.interrupt.send(Some(Interruption::Canceled));
+        }
+
+        (id, interrupted)
+    }
+
+    /// interrupt the in-flight transitions whose target state is one of `mode_dependency`
+    /// the first interruption wins
+    pub fn interrupt_transitions(
+        &self,
+        mode_dependency: &[ModeDependency],
+        interruption: Interruption,
+    ) {
+        for (function_group, transition) in self.transitions.lock().unwrap().iter() {
+            if !mode_dependency.iter().any(|mode| {
+                mode.function_group == *function_group && mode.state == transition.state
+            }) {
+                continue;
+            }
+            transition.interrupt.send_if_modified(|current| {
+                if current.is_some() {
+                    return false;
+                }
+                *current = Some(interruption);
+                true
+            });
+        }
+    }
+
+    /// reject every later transition and cancel the in-flight ones, e.g. SetState of a SM still
+    /// connected while EM stops every process
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        for transition in self.transitions.lock().unwrap().values() {
+            transition.interrupt.send_if_modified(|current| {
+                if current.is_some() {
+                    return false;
+                }
+                *current = Some(Interruption::Canceled);
+                true
+            });
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    pub fn end_transition(&self, function_group: &str, id: u64) {
+        let mut transitions = self.transitions.lock().unwrap();
+        if transitions
+            .get(function_group)
+            .is_some_and(|transition| transition.id == id)
+        {
+            transitions.remove(function_group);
+        }
+    }
+
+    pub fn is_in_transition(&self, function_group: &str) -> bool {
+        self.transitions.lock().unwrap().contains_key(function_group)
+    }
+
+    /// target state of the in-flight transition of the function group
+    pub fn transition_target(&self, function_group: &str) -> Option<String> {
+        self.transitions
+            .lock()
+            .unwrap()
+            .get(function_group)
+            .map(|transition| transition.state.clone())
+    }
+
+    /// held during the transition of the function group
+    pub fn transition_lock(&self, function_group: &str) -> Option<&Mutex<()>> {
+        self.transition_locks.get(function_group)
+    }
+
+    /// the function group changed to the Undefined Function Group State without SetState,
+    /// the event is pushed to every connected SM
+    pub async fn enter_undefined_state(&self, event: ExecutionErrorEvent) {
+        println!(
+            "{} is in the Undefined Function Group State, execution error {}",
+            event.function_group, event.execution_error
+        );
+        self.execution_errors
+            .lock()
+            .await
+            .insert(event.function_group.clone(), event.clone());
+        // no SM may be connected
+        let _ = self.execution_error_events.send(event);
+    }
+
+    /// a successful SetState defines the state of the function group again
+    pub async fn leave_undefined_state(&self, function_group: &str) {
+        self.execution_errors.lock().await.remove(function_group);
+    }
+
+    pub fn subscribe_execution_error_events(&self) -> broadcast::Receiver<ExecutionErrorEvent> {
+        self.execution_error_events.subscribe()
+    }
+}
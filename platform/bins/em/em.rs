@@ -1,17 +1,30 @@
 pub mod application;
 pub mod config;
+pub mod context;
 pub mod event;
 pub mod function_group_state;
+pub mod machine_action;
+pub mod output;
+pub mod resource_group;
 
 use anyhow::Result;
+use ara_exec::execution_client::OARA_EM_DOMAIN_SOCKET;
+use ara_exec::state_client::OARA_SM_DOMAIN_SOCKET;
+use std::process::ExitCode;
+use std::sync::Arc;
+use context::Context;
 use function_group_state::group::group;
-use crate::event::state_manager::{set_state, set_intial_state};
+use crate::event::state_manager::{set_state, set_intial_state, stop_all};
 use ara_exec::function_group::{get_machine_fg_state, STARTUP};
+use config::argument::MachineActionKind;
+use machine_action::{MachineActionBackend, RebootBackend, RecordingBackend, SystemdBackend};
+use resource_group::ResourceGroups;
+use tokio::signal::unix::{signal, Signal, SignalKind};
 
 /*
                                            Something Structure to manage function group state for every group
                                            HashMap<String, String> // Function Group / State
-                                               |- MachineFg, /Off, Startup, Restart, Shutdown ..
+                                               |- MachineFG, /Off, Startup, Restart, Shutdown ..
                                                |- Drving,    /Off, On, Verify ..
 
    request to chagen function group    Get current function group
@@ -33,17 +46,69 @@ use ara_exec::function_group::{get_machine_fg_state, STARTUP};
 
 */
 
+/// SIGTERM or SIGINT
+async fn wait_termination(sigterm: &mut Signal, sigint: &mut Signal) {
+    tokio::select! {
+        _ = sigterm.recv() => println!("SIGTERM is received"),
+        _ = sigint.recv() => println!("SIGINT is received"),
+    }
+}
+
+/// EM runs until SIGTERM or SIGINT, then every process is stopped within its exit timeout
+/// The exit code is failure if a process had to be killed.
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
+    // installed before any process is launched, the default action would orphan them
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+
     let arg = config::argument::parse()?;
     let machine_manifest = config::configuration::load_machine_manifest(arg.config.as_str())?;
     let execution_manifest = config::configuration::load_execution_manifest(
         arg.config.as_str(),
+        arg.ro_oara_root.as_str(),
         arg.rw_oara_root.as_str(),
     )?;
+    config::configuration::validate_executable(&execution_manifest)?;
 
-    let _ = config::configuration::validate_manifest(&machine_manifest, &execution_manifest)?;
-    let fg_hashmap = group(machine_manifest, execution_manifest)?;
+    config::configuration::validate_manifest(&machine_manifest, &execution_manifest)?;
+    let fg_hashmap = group(&machine_manifest, &execution_manifest)?;
+    let machine_action_backend: Arc<dyn MachineActionBackend> = match arg.machine_action {
+        MachineActionKind::Reboot => Arc::new(RebootBackend),
+        MachineActionKind::Systemd => Arc::new(SystemdBackend),
+        MachineActionKind::None => Arc::new(RecordingBackend::default()),
+    };
+    // cgroups are left untouched unless resource groups are configured
+    let resource_groups = if machine_manifest.resource_group.is_empty() {
+        None
+    } else {
+        Some(ResourceGroups::create(
+            arg.cgroup_root.as_str(),
+            &machine_manifest.resource_group,
+        )?)
+    };
+    let mut context = Context::new(
+        machine_manifest,
+        &execution_manifest,
+        fg_hashmap,
+        arg.ro_oara_root.as_str(),
+    )
+    .with_machine_action_backend(machine_action_backend);
+    if !arg.rw_oara_root.is_empty() {
+        context = context.with_rw_oara_root(arg.rw_oara_root.as_str());
+    }
+    if let Some(resource_groups) = resource_groups {
+        context = context.with_resource_groups(resource_groups);
+    }
+    if let Some(max_parallel_start) = arg.max_parallel_start {
+        context = context.with_max_parallel_start(max_parallel_start);
+    }
+    let context = Arc::new(context);
+
+    let _execution_handle = tokio::spawn(event::execution_manager::execution_receiver(
+        context.clone(),
+        OARA_EM_DOMAIN_SOCKET,
+    ));
 
     /*let (resp_tx, mut resp_rx) = mpsc::channel(1);
     let (tx, mut rx) = mpsc::channel::<event::RequestChangeState>(5);
@@ -68,15 +133,41 @@ async fn main() -> Result<()> {
             panic!("Channel might be broken")
         }
     }*/
-    match set_state(&fg_hashmap, get_machine_fg_state(STARTUP)) {
-        Ok(()) => {
-            set_intial_state(true);
+    // a signal during the Startup transition stops the processes it launched so far
+    let terminated = tokio::select! {
+        result = set_state(&context, get_machine_fg_state(STARTUP)) => {
+            match result {
+                Ok(()) => {
+                    set_intial_state(true);
+                }
+                Err(_) => {
+                    set_intial_state(false);
+                }
+            }
+            false
         }
-        Err(_) => {
-            set_intial_state(false);
+        _ = wait_termination(&mut sigterm, &mut sigint) => true,
+    };
+
+    if !terminated {
+        let state_handle = tokio::spawn(event::state_manager::state_receiver(
+            context.clone(),
+            OARA_SM_DOMAIN_SOCKET,
+        ));
+
+        wait_termination(&mut sigterm, &mut sigint).await;
+
+        // no more connection from SM, `stop_all` rejects SetState of the connected ones
+        state_handle.abort();
+        if let Err(error) = std::fs::remove_file(OARA_SM_DOMAIN_SOCKET) {
+            println!("failed to remove {} : {:?}", OARA_SM_DOMAIN_SOCKET, error);
         }
     }
 
-    let _handle = tokio::spawn(event::state_manager::state_receiver());
-    Ok(())
+    if stop_all(&context).await {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("some processes didn't stop within the exit timeout");
+        Ok(ExitCode::FAILURE)
+    }
 }
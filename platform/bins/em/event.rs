@@ -1,4 +1,5 @@
 pub mod execution_manager;
+pub mod process_monitor;
 pub mod state_manager;
 
 use thiserror::Error;
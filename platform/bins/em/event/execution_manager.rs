@@ -1 +1,201 @@
+use crate::application::ProcessState;
+use crate::context::Context;
+use anyhow::Result;
+use ara_exec::codec::{read_frame, write_frame, FrameError};
+use ara_exec::execution_client::{
+    ExecutionClientCommand, ExecutionClientError, ExecutionClientResponse, ExecutionState,
+};
+use libc::pid_t;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::{UnixListener, UnixStream};
 
+/*
+  .-----------------.                          .---------------------.
+  | Process         | ---[IPC(Domain Socket)]--> | execution_receiver |
+  | ExecutionClient |                          `---------------------`
+  `-----------------`                                     |
+                                                          V
+                                              .-----------------.
+                                              | COMMON RESOURCE |
+                                              `-----------------`
+    The reporting process is identified by the peer credential(pid) of the connection
+*/
+
+/// Starting -> Running
+pub async fn report_execution_state(
+    context: &Context,
+    pid: pid_t,
+    state: ExecutionState,
+) -> Result<(), ExecutionClientError> {
+    let mut processes = context.processes.lock().await;
+    let process = processes
+        .values_mut()
+        .find(|process| process.pid == Some(pid))
+        .ok_or(ExecutionClientError::CommunicationError)?;
+
+    // unable to report state for Non-reporting Process
+    if !process.execution_manifest.reporting_behavior {
+        return Err(ExecutionClientError::CommunicationError);
+    }
+
+    match (state, process.process_state) {
+        (ExecutionState::Running, ProcessState::Starting) => {
+            process.process_state = ProcessState::Running;
+        }
+        _ => {
+            return Err(ExecutionClientError::InvalidTransition);
+        }
+    }
+    drop(processes);
+
+    context.notify_process_state_changed();
+    Ok(())
+}
+
+async fn handle_connection(context: Arc<Context>, mut stream: UnixStream) -> Result<()> {
+    let pid = stream.peer_cred()?.pid();
+
+    loop {
+        let request_command = match read_frame::<_, ExecutionClientCommand>(&mut stream).await {
+            Ok(command) => command,
+            // closed by the client
+            Err(FrameError::Closed) => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+        let response = match request_command {
+            ExecutionClientCommand::ReportExecutionState(state) => {
+                let result = match pid {
+                    Some(pid) => report_execution_state(&context, pid, state).await,
+                    None => Err(ExecutionClientError::CommunicationError),
+                };
+                ExecutionClientResponse::ReportExecutionState(result)
+            }
+        };
+        write_frame(&mut stream, &response).await?;
+    }
+}
+
+/// Serve ExecutionClient of every process for the whole EM lifetime
+pub async fn execution_receiver<P: AsRef<Path>>(context: Arc<Context>, socket_path: P) -> Result<()> {
+    let socket_path = socket_path.as_ref();
+    if tokio::fs::metadata(socket_path).await.is_ok() {
+        tokio::fs::remove_file(socket_path).await?;
+    }
+
+    let listener = UnixListener::bind(socket_path)?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let context = context.clone();
+        tokio::spawn(async move {
+            if let Err(error) = handle_connection(context, stream).await {
+                println!("execution client connection error : {:?}", error);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_group_state::group::group;
+    use ara_exec::execution_client::ExecutionClient;
+    use ara_exec::manifest::execution_manifest::ExecutionManifest;
+    use ara_exec::manifest::machine_manifest::MachineManifest;
+    use tokio::time::{sleep, Duration};
+
+    fn make_context(execution_manifests: &[&str]) -> Context {
+        let machine_manifest = MachineManifest::from("").unwrap();
+        let execution_manifests: Vec<_> = execution_manifests
+            .iter()
+            .map(|manifest| ExecutionManifest::from(manifest).unwrap())
+            .collect();
+        let fg_hashmap = group(&machine_manifest, &execution_manifests).unwrap();
+        Context::new(machine_manifest, &execution_manifests, fg_hashmap, "")
+    }
+
+    #[tokio::test]
+    async fn report_running() {
+        let context = make_context(&[
+            r#"
+            name: APP1
+            reporting_behavior: true
+            "#,
+            r#"
+            name: APP2
+            "#,
+        ]);
+        for (name, pid) in [("APP1", 100), ("APP2", 200)] {
+            let mut processes = context.processes.lock().await;
+            let process = processes.get_mut(name).unwrap();
+            process.pid = Some(pid);
+            process.process_state = ProcessState::Starting;
+        }
+
+        // unknown process
+        assert_eq!(
+            report_execution_state(&context, 300, ExecutionState::Running).await,
+            Err(ExecutionClientError::CommunicationError)
+        );
+        // Non-reporting process
+        assert_eq!(
+            report_execution_state(&context, 200, ExecutionState::Running).await,
+            Err(ExecutionClientError::CommunicationError)
+        );
+
+        assert!(report_execution_state(&context, 100, ExecutionState::Running)
+            .await
+            .is_ok());
+        assert_eq!(
+            context.processes.lock().await.get("APP1").unwrap().process_state,
+            ProcessState::Running
+        );
+        // already in Running
+        assert_eq!(
+            report_execution_state(&context, 100, ExecutionState::Running).await,
+            Err(ExecutionClientError::InvalidTransition)
+        );
+    }
+
+    #[tokio::test]
+    async fn execution_client() {
+        let socket_path = std::env::temp_dir().join("execution_manager-t1");
+        let context = Arc::new(make_context(&[r#"
+            name: APP1
+            reporting_behavior: true
+            "#]));
+        {
+            // this test process plays APP1
+            let mut processes = context.processes.lock().await;
+            let process = processes.get_mut("APP1").unwrap();
+            process.pid = Some(std::process::id() as pid_t);
+            process.process_state = ProcessState::Starting;
+        }
+
+        let handle = tokio::spawn(execution_receiver(context.clone(), socket_path.clone()));
+        // wait a second to create domain socket
+        sleep(Duration::from_millis(10)).await;
+
+        let execution_client = ExecutionClient::new().with_socket_path(&socket_path);
+        assert!(execution_client
+            .report_execution_state(ExecutionState::Running)
+            .await
+            .is_ok());
+        assert_eq!(
+            context.processes.lock().await.get("APP1").unwrap().process_state,
+            ProcessState::Running
+        );
+
+        let error = execution_client
+            .report_execution_state(ExecutionState::Running)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.downcast_ref::<ExecutionClientError>().unwrap(),
+            &ExecutionClientError::InvalidTransition
+        );
+
+        handle.abort();
+    }
+}
@@ -0,0 +1,217 @@
+use crate::application::{Child, ExitStatus, Process, ProcessState};
+use crate::context::{Context, Interruption, StateHashMap};
+use crate::event::state_manager::wait_running;
+use crate::output;
+use anyhow::Result;
+use ara_exec::execution_client::ExecutionErrorEvent;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio::time::sleep;
+
+/*
+  .------------.  pidfd   .--------.  -> Terminated   .-----------------.
+  | process    | -------> | reaper | o--------------> | COMMON RESOURCE |
+  `------------`  exits   `--------`                  `-----------------`
+                             o  o                               o
+  in-flight transition  <----`  | restart up to number_of_restart | ExecutionErrorEvent
+  FailedUnexpectedTermination   V                                 V
+                    Undefined Function Group State      state_receiver ----> SM
+*/
+
+/// What to do with a process which terminated unexpectedly
+enum Supervision {
+    /// its function group states are not active anymore, or the in-flight transition fails
+    Ignore,
+    /// the `attempt`th restart
+    Restart(u32),
+    /// the restarts are used up
+    Escalate(Vec<ExecutionErrorEvent>),
+}
+
+/// function groups whose current state the process belongs to
+fn affected_function_groups(process: &Process, states: &StateHashMap) -> Vec<String> {
+    process
+        .execution_manifest
+        .mode_dependency
+        .iter()
+        .filter(|mode| {
+            states
+                .get(&mode.function_group)
+                .is_some_and(|current| *current == mode.state)
+        })
+        .map(|mode| mode.function_group.clone())
+        .collect()
+}
+
+fn supervise(context: &Context, process: &mut Process, states: &StateHashMap) -> Supervision {
+    let function_groups = affected_function_groups(process, states);
+    if function_groups.is_empty()
+        || function_groups
+            .iter()
+            .any(|function_group| context.is_in_transition(function_group))
+    {
+        return Supervision::Ignore;
+    }
+
+    if process.restart_count < process.execution_manifest.number_of_restart.max(0) as u32 {
+        process.restart_count += 1;
+        return Supervision::Restart(process.restart_count);
+    }
+
+    Supervision::Escalate(
+        function_groups
+            .into_iter()
+            .map(|function_group| ExecutionErrorEvent {
+                execution_error: process.execution_error(),
+                function_group,
+            })
+            .collect(),
+    )
+}
+
+async fn escalate(context: &Context, name: &str, events: Vec<ExecutionErrorEvent>) {
+    println!("{} is not restarted anymore", name);
+    for event in events {
+        context.enter_undefined_state(event).await;
+    }
+}
+
+/// Relaunch the process after its backoff, it has to report Running within its enter timeout
+/// again. A failed attempt is supervised like an unexpected termination.
+async fn restart(context: Arc<Context>, name: String, mut attempt: u32) {
+    loop {
+        let backoff = match context.processes.lock().await.get(&name) {
+            Some(process) => process.restart_backoff(attempt),
+            None => return,
+        };
+        sleep(backoff).await;
+
+        let supervision = match relaunch(&context, &name, attempt).await {
+            Some(supervision) => supervision,
+            None => return,
+        };
+        match supervision {
+            Supervision::Ignore => return,
+            Supervision::Restart(next) => attempt = next,
+            Supervision::Escalate(events) => {
+                escalate(&context, &name, events).await;
+                return;
+            }
+        }
+    }
+}
+
+/// None if the process is Running again, or doesn't need to be relaunched anymore
+async fn relaunch(context: &Arc<Context>, name: &str, attempt: u32) -> Option<Supervision> {
+    let states = context.states.lock().await.clone();
+    {
+        let mut processes = context.processes.lock().await;
+        let process = processes.get_mut(name)?;
+        // launched by a transition, or its state is not active anymore
+        if process.is_active() || affected_function_groups(process, &states).is_empty() {
+            return None;
+        }
+
+        println!(
+            "restart {} ({}/{})",
+            name, attempt, process.execution_manifest.number_of_restart
+        );
+        if let Err(error) = launch(context, name, process) {
+            println!("{:?}", error);
+            return Some(supervise(context, process, &states));
+        }
+    }
+    context.notify_process_state_changed();
+
+    // never interrupted
+    let (_, interrupted) = watch::channel(None);
+    let error = wait_running(context, name, &interrupted).await.err()?;
+    println!("restart of {} failed : {:?}", name, error);
+
+    let states = context.states.lock().await.clone();
+    let mut processes = context.processes.lock().await;
+    Some(supervise(context, processes.get_mut(name)?, &states))
+}
+
+/// Launch the process, a reaper task updates its state when it exits
+pub fn launch(context: &Arc<Context>, name: &str, process: &mut Process) -> Result<()> {
+    let cgroup = context.cgroup(process);
+    let pipes = process.start(
+        context.executable(process),
+        &context.machine_manifest,
+        cgroup.as_deref(),
+    )?;
+    output::capture(
+        name,
+        &process.execution_manifest.log,
+        context.log_dir(name),
+        pipes,
+    );
+
+    // Non-reporting process is regarded as Running once it is spawned
+    if !process.execution_manifest.reporting_behavior {
+        process.process_state = ProcessState::Running;
+    }
+
+    if let Some(child) = process.child.clone() {
+        tokio::spawn(reap(context.clone(), name.to_owned(), child));
+    }
+    Ok(())
+}
+
+/// The exit is expected if the process was Terminating
+/// A Starting process fails the transition or the restart waiting for it to report Running.
+/// A Running process fails the in-flight transition of its function groups, or is supervised.
+async fn reap(context: Arc<Context>, name: String, child: Child) {
+    let exit_status = child.wait().await;
+    let states = context.states.lock().await.clone();
+
+    let supervision = {
+        let mut processes = context.processes.lock().await;
+        let Some(process) = processes.get_mut(&name) else {
+            return;
+        };
+        // already cleaned up by the transition which stopped it
+        if process.pid != Some(child.pid) {
+            return;
+        }
+
+        let exit_status = match (exit_status, &process.execution_manifest.resource_group) {
+            (ExitStatus::Signaled(libc::SIGKILL), Some(resource_group))
+                if context
+                    .resource_groups()
+                    .is_some_and(|groups| groups.take_oom_kill(resource_group)) =>
+            {
+                ExitStatus::OomKilled
+            }
+            _ => exit_status,
+        };
+        println!("{} exited with {:?}", name, exit_status);
+        let previous_state = process.process_state;
+        process.process_state = ProcessState::Terminated;
+        process.exit_status = Some(exit_status);
+        process.pid = None;
+        process.child = None;
+
+        // the transition launching a one-shot process checks its exit status
+        if process.one_shot {
+            Supervision::Ignore
+        } else if previous_state == ProcessState::Running {
+            println!("{} terminated unexpectedly", name);
+            context.interrupt_transitions(
+                &process.execution_manifest.mode_dependency,
+                Interruption::UnexpectedTermination,
+            );
+            supervise(&context, process, &states)
+        } else {
+            Supervision::Ignore
+        }
+    };
+    context.notify_process_state_changed();
+
+    match supervision {
+        Supervision::Ignore => {}
+        Supervision::Restart(attempt) => restart(context, name, attempt).await,
+        Supervision::Escalate(events) => escalate(&context, &name, events).await,
+    }
+}
@@ -1,26 +1,55 @@
 //use super::RequestChangeState;
-use crate::function_group_state::group::FunctionGroupHashMap;
+use crate::application::{kill, terminate, Child, ExitStatus, ProcessState};
+use crate::context::{Context, Interruption};
+use crate::event::process_monitor::launch;
+use crate::function_group_state::group::DependencyGraph;
+use crate::machine_action::MachineAction;
 //use std::io::{self, Read, Write};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::UnixListener;
+use tokio::net::{UnixListener, UnixStream};
 //use tokio::sync::mpsc;
 use anyhow::Result;
+use ara_exec::manifest::execution_manifest::ExecutionManifest;
+use ara_exec::manifest::machine_manifest::{MACHINE_FG, OFF, RUNNING, SHUTDOWN, TERMINATED};
 // /use serde::{Deserialize, Serialize};
+use ara_exec::codec::{read_frame, write_frame, FrameError};
+use ara_exec::execution_client::ExecutionErrorEvent;
 use ara_exec::{
     function_group::{
         FunctionGroupState,
     },
     state_client::{
-        OARA_SM_DOMAIN_SOCKET,
         SmClientCommand,
+        GetExecutionErrorError,
+        GetProcessStatusError,
         InitialStateError,
+        ProcessStatus,
+        SetStateError,
+        SmMessage,
+        SmRequest,
         SmResponse,
     },
 };
 use once_cell::sync::OnceCell;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinSet;
+use tokio::time::{sleep_until, Instant};
+use thiserror::Error;
 
 static INITIAL_STATE: OnceCell<bool> = OnceCell::new();
 
+#[derive(Debug, Error)]
+enum StartError {
+    #[error("One-shot process {0} didn't exit successfully : {1:?}")]
+    OneShotFailed(String, Option<ExitStatus>),
+    #[error("One-shot process {0} doesn't exit within the enter timeout")]
+    OneShotTimeout(String),
+}
+
 pub fn set_intial_state(value: bool) {
     INITIAL_STATE.set(value).expect("INITIAL_STATE can only be set once!");
 }
@@ -43,7 +72,7 @@ pub fn get_intial_state() -> bool {
   .---------------.                  .----------------.
   | main thread   |                  | state_receiver |
   `---------------`                  `----------------`
-         ^ MachineFg/Startup                 ^ o
+         ^ MachineFG/Startup                 ^ o
          |                                   | |
          |                                   | |
          +-----------------------------------` |
@@ -74,72 +103,2319 @@ pub fn get_intial_state() -> bool {
     }
 }*/
 
-pub fn set_state(fg_hashmap: &FunctionGroupHashMap, fg_state: FunctionGroupState) -> Result<()> {
+/// Change the state of a function group
+/// Processes which don't belong to the target state are terminated in the reversed order of the
+/// current state, then processes of the target state are launched in the dependency order
+/// computed by `group()`. Processes independent of each other start in parallel, up to the
+/// maximum parallelism of the context.
+///
+/// A newer request for the same function group cancels the in-flight one, which returns
+/// `SetStateError::Canceled` at its next cancellation point (before stopping or launching a
+/// process, or while waiting for a process to report Running). The newer one starts once the
+/// canceled one has returned. A Running process of the function group which terminates by itself
+/// fails the in-flight one with `SetStateError::FailedUnexpectedTermination` the same way.
+///
+/// MachineFG Shutdown and Restart stop every other function group first, then the machine
+/// action backend of the context powers off or reboots the machine.
+///
+/// A transition which the transition table of the function group doesn't allow from its current
+/// state, nor from the target of the in-flight transition, is rejected with
+/// `SetStateError::InvalidTransition` without canceling the in-flight one. The current state is
+/// checked again once the in-flight transition has returned, before any process is touched.
+///
+/// A process which another process of the target state depends on as Terminated is a one-shot
+/// process of that state, it has to exit with status 0 within its enter timeout. Otherwise the
+/// transition fails with a `StartError` naming it. SM receives it as `SetStateError::Failed`
+/// without the name, so the name is only in the log of EM. If it is still running long-lived
+/// for the current state, it is stopped and launched again.
+pub async fn set_state(context: &Arc<Context>, fg_state: FunctionGroupState) -> Result<()> {
+    if is_prohibited_transition(&fg_state) {
+        return Err(SetStateError::InvalidTransition.into());
+    }
+
+    let graph = context
+        .fg_hashmap
+        .get(&fg_state.function_group)
+        .and_then(|state_hashmap| state_hashmap.get(&fg_state.function_group_state))
+        .ok_or(SetStateError::MetamodelError)?;
+
+    // a forbidden request leaves the in-flight transition alone
+    if !is_allowed_transition(context, &fg_state).await
+        && !context
+            .transition_target(&fg_state.function_group)
+            .is_some_and(|target| is_allowed_from(context, &fg_state, &target))
+    {
+        println!(
+            "{}.{} is not allowed from the current state",
+            fg_state.function_group, fg_state.function_group_state
+        );
+        return Err(SetStateError::InvalidTransition.into());
+    }
+
+    let (id, interrupted) = context.begin_transition(&fg_state);
+    let result = transition(context, &fg_state, graph, &interrupted).await;
+    context.end_transition(&fg_state.function_group, id);
+
+    // SM only receives SetStateError::Failed, the failed process is named here
+    if let Err(error) = &result {
+        if let Some(error) = error.downcast_ref::<StartError>() {
+            println!(
+                "transition to {}.{} failed : {}",
+                fg_state.function_group, fg_state.function_group_state, error
+            );
+        }
+    }
+
+    result
+}
+
+async fn transition(
+    context: &Arc<Context>,
+    fg_state: &FunctionGroupState,
+    graph: &DependencyGraph,
+    interrupted: &watch::Receiver<Option<Interruption>>,
+) -> Result<()> {
+    let _transition = context
+        .transition_lock(&fg_state.function_group)
+        .ok_or(SetStateError::MetamodelError)?
+        .lock()
+        .await;
+    // canceled by another request while waiting for the previous one
+    check_interrupted(interrupted)?;
+    // e.g. SetState of a SM still connected while `stop_all` stops every process
+    if context.is_shutting_down() {
+        println!(
+            "{}.{} is rejected, EM is shutting down",
+            fg_state.function_group, fg_state.function_group_state
+        );
+        return Err(SetStateError::Canceled.into());
+    }
+    // from the state the previous transition left, not from the one when the request arrived
+    if !is_allowed_transition(context, fg_state).await {
+        println!(
+            "{}.{} is not allowed from the current state",
+            fg_state.function_group, fg_state.function_group_state
+        );
+        return Err(SetStateError::InvalidTransition.into());
+    }
+
+    let machine_action = if fg_state.function_group == MACHINE_FG {
+        MachineAction::of_state(&fg_state.function_group_state)
+    } else {
+        None
+    };
+    if machine_action.is_some() {
+        stop_function_groups(context, Some(fg_state), interrupted).await?;
+    }
 
-    // change to off state except MachineFg
+    stop_processes(context, &fg_state.function_group, Some(fg_state), interrupted).await?;
+    start_processes(context, graph, interrupted).await?;
+    // e.g. a process terminated after reporting Running
+    check_interrupted(interrupted)?;
 
-    match fg_hashmap.get(&fg_state.function_group) {
-        Some(state_hashmap) => {
-            match state_hashmap.get(&fg_state.function_group_state) {
-                Some(manifests) => {
-                    for _manifest in manifests {
-                        // TODO
-                        //manifest.
+    context.states.lock().await.insert(
+        fg_state.function_group.clone(),
+        fg_state.function_group_state.clone(),
+    );
+    // the processes are up again, e.g. one which used up its restarts in the former state
+    {
+        let mut processes = context.processes.lock().await;
+        for manifest in graph.manifests() {
+            if let Some(process) = processes.get_mut(&manifest.name) {
+                process.restart_count = 0;
+            }
+        }
+    }
+    context
+        .leave_undefined_state(&fg_state.function_group)
+        .await;
+
+    if let Some(action) = machine_action {
+        println!("{:?} the machine", action);
+        context.machine_action_backend().execute(action).map_err(|error| {
+            println!("{:?}", error);
+            SetStateError::Failed
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Stop every process when EM itself goes down, the other function groups first then MachineFG
+/// In-flight transitions are canceled and later SetState requests are rejected with
+/// `SetStateError::Canceled`. The machine action backend is not invoked.
+/// returns false if a process had to be killed
+pub async fn stop_all(context: &Context) -> bool {
+    context.begin_shutdown();
+    // never interrupted
+    let (_, interrupted) = watch::channel(None);
+    let mut clean = match stop_function_groups(context, None, &interrupted).await {
+        Ok(clean) => clean,
+        Err(error) => {
+            println!("{:?}", error);
+            false
+        }
+    };
+
+    let shutdown = FunctionGroupState {
+        function_group: MACHINE_FG.to_owned(),
+        function_group_state: SHUTDOWN.to_owned(),
+    };
+    let (id, _) = context.begin_transition(&shutdown);
+    if let Some(lock) = context.transition_lock(MACHINE_FG) {
+        let _transition = lock.lock().await;
+        clean &= stop_processes(context, MACHINE_FG, None, &interrupted)
+            .await
+            .unwrap_or(false);
+    }
+    context.end_transition(MACHINE_FG, id);
+
+    clean
+}
+
+/// Stop the processes of every function group except MachineFG, which go to Off if they declare
+/// it, a function group before the ones it depends on. In-flight transitions of them are canceled. Processes which also belong to `machine_state`
+/// are kept.
+/// returns false if a process had to be killed
+async fn stop_function_groups(
+    context: &Context,
+    machine_state: Option<&FunctionGroupState>,
+    interrupted: &watch::Receiver<Option<Interruption>>,
+) -> Result<bool, SetStateError> {
+    let function_groups = stop_order(context).await;
+
+    let mut clean = true;
+    for function_group in function_groups {
+        let off = FunctionGroupState {
+            function_group: function_group.clone(),
+            function_group_state: OFF.to_owned(),
+        };
+        let (id, _) = context.begin_transition(&off);
+        let result = stop_function_group(context, &off, machine_state, interrupted).await;
+        context.end_transition(&function_group, id);
+        clean &= result?;
+    }
+
+    Ok(clean)
+}
+
+/// function groups except MachineFG, each one before the function groups it depends on
+/// A function group depends on another one if one of its processes has an app_dependency on a
+/// process of the other one. Function groups which don't depend on each other, or which depend
+/// on each other both ways, keep the name order.
+async fn stop_order(context: &Context) -> Vec<String> {
+    let mut function_groups: Vec<String> = context
+        .machine_manifest
+        .function_group_set
+        .keys()
+        .filter(|function_group| *function_group != MACHINE_FG)
+        .cloned()
+        .collect();
+    function_groups.sort();
+
+    // (dependent, dependency)
+    let mut dependencies = HashSet::new();
+    {
+        let processes = context.processes.lock().await;
+        let groups_of = |name: &str| -> HashSet<String> {
+            processes
+                .get(name)
+                .map(|process| {
+                    process
+                        .execution_manifest
+                        .mode_dependency
+                        .iter()
+                        .map(|mode| mode.function_group.clone())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        for process in processes.values() {
+            let dependents = groups_of(&process.execution_manifest.name);
+            for dependency in &process.execution_manifest.app_dependency {
+                for function_group in groups_of(&dependency.app) {
+                    for dependent in &dependents {
+                        if *dependent != function_group {
+                            dependencies.insert((dependent.clone(), function_group.clone()));
+                        }
                     }
                 }
-                None => {
-                    // TBD.
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(function_groups.len());
+    while !function_groups.is_empty() {
+        // the first one no remaining function group depends on, the first one on a cycle
+        let next = function_groups
+            .iter()
+            .position(|function_group| {
+                !function_groups.iter().any(|dependent| {
+                    dependencies.contains(&(dependent.clone(), function_group.clone()))
+                })
+            })
+            .unwrap_or(0);
+        order.push(function_groups.remove(next));
+    }
+    order
+}
+
+async fn stop_function_group(
+    context: &Context,
+    off: &FunctionGroupState,
+    machine_state: Option<&FunctionGroupState>,
+    interrupted: &watch::Receiver<Option<Interruption>>,
+) -> Result<bool, SetStateError> {
+    let _transition = context
+        .transition_lock(&off.function_group)
+        .ok_or(SetStateError::MetamodelError)?
+        .lock()
+        .await;
+
+    let clean = stop_processes(context, &off.function_group, machine_state, interrupted).await?;
+
+    let declares_off = context
+        .machine_manifest
+        .function_group_set
+        .get(&off.function_group)
+        .is_some_and(|mode| mode.mode.iter().any(|mode| mode == OFF));
+    let mut states = context.states.lock().await;
+    if declares_off {
+        states.insert(off.function_group.clone(), OFF.to_owned());
+    } else {
+        states.remove(&off.function_group);
+    }
+
+    Ok(clean)
+}
+
+fn interruption_error(interruption: Interruption) -> SetStateError {
+    match interruption {
+        Interruption::Canceled => SetStateError::Canceled,
+        Interruption::UnexpectedTermination => SetStateError::FailedUnexpectedTermination,
+    }
+}
+
+fn check_interrupted(
+    interrupted: &watch::Receiver<Option<Interruption>>,
+) -> Result<(), SetStateError> {
+    match *interrupted.borrow() {
+        Some(interruption) => Err(interruption_error(interruption)),
+        None => Ok(()),
+    }
+}
+
+/// e.g. Off state for MachineFG
+fn is_prohibited_transition(fg_state: &FunctionGroupState) -> bool {
+    fg_state.function_group == MACHINE_FG && fg_state.function_group_state == OFF
+}
+
+/// by the transition table of the function group in the machine manifest, from its current state
+async fn is_allowed_transition(context: &Context, fg_state: &FunctionGroupState) -> bool {
+    if !context
+        .machine_manifest
+        .function_group_set
+        .contains_key(&fg_state.function_group)
+    {
+        return false;
+    }
+    match context.states.lock().await.get(&fg_state.function_group) {
+        Some(current) => is_allowed_from(context, fg_state, current),
+        None => true,
+    }
+}
+
+/// by the transition table of the function group in the machine manifest, from `from`
+fn is_allowed_from(context: &Context, fg_state: &FunctionGroupState, from: &str) -> bool {
+    context
+        .machine_manifest
+        .function_group_set
+        .get(&fg_state.function_group)
+        .is_some_and(|mode| mode.allows(from, &fg_state.function_group_state))
+}
+
+/// processes of `function_group` whose mode_dependency doesn't include `target`, which is the
+/// new state of `function_group` or MachineFG Shutdown/Restart. Every process of it if None.
+/// Long-running processes which are one-shot in `target` are stopped too, to be launched again.
+async fn processes_to_stop(
+    context: &Context,
+    function_group: &str,
+    target: Option<&FunctionGroupState>,
+) -> Vec<String> {
+    // reversed start order of the current state first, dependents before their dependencies
+    let mut order: Vec<String> = Vec::new();
+    if let Some(current) = context.states.lock().await.get(function_group) {
+        if let Some(manifests) = context
+            .fg_hashmap
+            .get(function_group)
+            .and_then(|state_hashmap| state_hashmap.get(current))
+        {
+            order.extend(
+                manifests
+                    .manifests()
+                    .iter()
+                    .rev()
+                    .map(|manifest| manifest.name.clone()),
+            );
+        }
+    }
+
+    let relaunched = target
+        .and_then(|target| {
+            context
+                .fg_hashmap
+                .get(&target.function_group)
+                .and_then(|state_hashmap| state_hashmap.get(&target.function_group_state))
+        })
+        .map(|graph| one_shots(graph.manifests()))
+        .unwrap_or_default();
+
+    let processes = context.processes.lock().await;
+    let mut rest: Vec<&String> = processes.keys().filter(|name| !order.contains(name)).collect();
+    rest.sort();
+    order.extend(rest.into_iter().cloned());
+
+    order
+        .into_iter()
+        .filter(|name| {
+            processes.get(name).is_some_and(|process| {
+                let mode_dependency = &process.execution_manifest.mode_dependency;
+                process.is_active()
+                    && mode_dependency
+                        .iter()
+                        .any(|mode| mode.function_group == function_group)
+                    && (!target.is_some_and(|target| {
+                        mode_dependency.iter().any(|mode| mode.matches(target))
+                    }) || (!process.one_shot && relaunched.contains(name.as_str())))
+            })
+        })
+        .collect()
+}
+
+/// returns false if a process had to be killed
+async fn stop_processes(
+    context: &Context,
+    function_group: &str,
+    target: Option<&FunctionGroupState>,
+    interrupted: &watch::Receiver<Option<Interruption>>,
+) -> Result<bool, SetStateError> {
+    let mut clean = true;
+    for name in processes_to_stop(context, function_group, target).await {
+        check_interrupted(interrupted)?;
+        let (child, timeout) = {
+            let mut processes = context.processes.lock().await;
+            let process = processes.get_mut(&name).unwrap();
+            process.process_state = ProcessState::Terminating;
+            (process.child.clone(), process.exit_timeout(&context.machine_manifest))
+        };
+
+        if let Some(child) = &child {
+            clean &= terminate(child, timeout).await;
+        }
+
+        // the reaper may have done it already
+        let mut processes = context.processes.lock().await;
+        let process = processes.get_mut(&name).unwrap();
+        if process.pid == child.as_ref().map(|child| child.pid) {
+            process.process_state = ProcessState::Terminated;
+            process.exit_status = child.and_then(|child| child.exit_status());
+            process.pid = None;
+            process.child = None;
+        }
+    }
+
+    Ok(clean)
+}
+
+/// wait until the process reports Running within its enter timeout
+/// the process group is killed if it doesn't
+/// the process is left as it is if the transition is interrupted
+pub async fn wait_running(
+    context: &Context,
+    name: &str,
+    interrupted: &watch::Receiver<Option<Interruption>>,
+) -> Result<(), SetStateError> {
+    let mut interrupted = interrupted.clone();
+    let (child, deadline) = {
+        let processes = context.processes.lock().await;
+        let process = processes.get(name).ok_or(SetStateError::MetamodelError)?;
+        let started_at = process.started_at.unwrap_or_else(Instant::now);
+        (
+            process.child.clone(),
+            started_at + process.enter_timeout(&context.machine_manifest),
+        )
+    };
+
+    loop {
+        let notified = context.process_state_changed();
+        {
+            let processes = context.processes.lock().await;
+            let process = processes.get(name).ok_or(SetStateError::MetamodelError)?;
+            match process.process_state {
+                ProcessState::Running => return Ok(()),
+                // updated by the reaper
+                ProcessState::Idle | ProcessState::Terminated => {
+                    println!("{} terminated before reporting Running", name);
+                    return Err(SetStateError::FailedUnexpectedTerminationOnEnter);
                 }
+                ProcessState::Starting | ProcessState::Terminating => {}
             }
         }
-        None => {
-            // TBD.
+
+        tokio::select! {
+            _ = notified => {}
+            Ok(interruption) = interrupted.wait_for(Option::is_some) => {
+                return Err(interruption_error(interruption.unwrap()));
+            }
+            _ = sleep_until(deadline) => {
+                break;
+            }
         }
     }
 
+    println!("{} doesn't report Running within the enter timeout", name);
+    kill_timed_out(context, name, child).await;
+
+    Err(SetStateError::Failed)
+}
+
+/// kill the process group of a process which missed its enter timeout
+async fn kill_timed_out(context: &Context, name: &str, child: Option<Child>) {
+    if let Some(child) = &child {
+        kill(child).await;
+    }
+    let mut processes = context.processes.lock().await;
+    if let Some(process) = processes.get_mut(name) {
+        if process.pid == child.as_ref().map(|child| child.pid) {
+            process.process_state = ProcessState::Terminated;
+            process.exit_status = child.and_then(|child| child.exit_status());
+            process.pid = None;
+            process.child = None;
+        }
+    }
+    drop(processes);
+    context.notify_process_state_changed();
+}
+
+/// wait until the one-shot process exits with status 0 within its enter timeout
+/// the process group is killed if it doesn't exit in time
+async fn wait_terminated(
+    context: &Context,
+    name: &str,
+    interrupted: &watch::Receiver<Option<Interruption>>,
+) -> Result<()> {
+    let mut interrupted = interrupted.clone();
+    let (child, deadline) = {
+        let processes = context.processes.lock().await;
+        let process = processes.get(name).ok_or(SetStateError::MetamodelError)?;
+        let started_at = process.started_at.unwrap_or_else(Instant::now);
+        (
+            process.child.clone(),
+            started_at + process.enter_timeout(&context.machine_manifest),
+        )
+    };
+
+    loop {
+        let notified = context.process_state_changed();
+        {
+            let processes = context.processes.lock().await;
+            let process = processes.get(name).ok_or(SetStateError::MetamodelError)?;
+            match (process.process_state, process.exit_status) {
+                (ProcessState::Starting | ProcessState::Running, _) => {}
+                (_, Some(ExitStatus::Exited(0))) => return Ok(()),
+                (_, exit_status) => {
+                    return Err(StartError::OneShotFailed(name.to_owned(), exit_status).into());
+                }
+            }
+        }
+
+        tokio::select! {
+            _ = notified => {}
+            Ok(interruption) = interrupted.wait_for(Option::is_some) => {
+                return Err(interruption_error(interruption.unwrap()).into());
+            }
+            _ = sleep_until(deadline) => {
+                break;
+            }
+        }
+    }
+
+    kill_timed_out(context, name, child).await;
+    Err(StartError::OneShotTimeout(name.to_owned()).into())
+}
+
+/// processes depended on as Terminated in the state of `manifests`, they may be long-running in
+/// the others
+fn one_shots(manifests: &[ExecutionManifest]) -> HashSet<&str> {
+    let names: HashSet<&str> = manifests
+        .iter()
+        .map(|manifest| manifest.name.as_str())
+        .collect();
+    manifests
+        .iter()
+        .flat_map(|manifest| &manifest.app_dependency)
+        .filter(|dependency| {
+            dependency.state == TERMINATED && names.contains(dependency.app.as_str())
+        })
+        .map(|dependency| dependency.app.as_str())
+        .collect()
+}
+
+/// launch processes in the start order, a process is released as soon as its app_dependency are
+/// Running or Terminated, and at most `max_parallel_start` of the context are starting at once
+/// The transition is done when every process is Running, and every one-shot process exited with
+/// status 0.
+async fn start_processes(
+    context: &Arc<Context>,
+    graph: &DependencyGraph,
+    interrupted: &watch::Receiver<Option<Interruption>>,
+) -> Result<()> {
+    let manifests = graph.manifests();
+    let names: HashSet<&str> = manifests
+        .iter()
+        .map(|manifest| manifest.name.as_str())
+        .collect();
+
+    let one_shots = one_shots(manifests);
+
+    // process / app_dependency which are not Running or Terminated yet
+    let mut waiting: Vec<(&ExecutionManifest, Vec<&str>)> = manifests
+        .iter()
+        .map(|manifest| {
+            let prerequisites = manifest
+                .app_dependency
+                .iter()
+                .filter(|dependency| {
+                    matches!(dependency.state.as_str(), RUNNING | TERMINATED)
+                        && names.contains(dependency.app.as_str())
+                })
+                .map(|dependency| dependency.app.as_str())
+                .collect();
+            (manifest, prerequisites)
+        })
+        .collect();
+
+    // the processes left are aborted if one fails, they are stopped by the next transition
+    let mut starting = JoinSet::new();
+    loop {
+        while starting.len() < context.max_parallel_start() {
+            let Some(position) = waiting
+                .iter()
+                .position(|(_, prerequisites)| prerequisites.is_empty())
+            else {
+                break;
+            };
+            let (manifest, _) = waiting.remove(position);
+            check_interrupted(interrupted)?;
+            starting.spawn(start_process(
+                context.clone(),
+                manifest.name.clone(),
+                one_shots.contains(manifest.name.as_str()),
+                interrupted.clone(),
+            ));
+        }
+
+        let Some(result) = starting.join_next().await else {
+            break;
+        };
+        let name = result??;
+        for (_, prerequisites) in &mut waiting {
+            prerequisites.retain(|app| *app != name);
+        }
+    }
 
     Ok(())
 }
 
-pub async fn state_receiver() -> Result<()> {
-    let socket_path = OARA_SM_DOMAIN_SOCKET;
+/// launch the process unless another state did, and wait until it's Running, or until it exits
+/// if it's a one-shot process of the state
+/// returns the name of the process
+async fn start_process(
+    context: Arc<Context>,
+    name: String,
+    one_shot: bool,
+    interrupted: watch::Receiver<Option<Interruption>>,
+) -> Result<String> {
+    check_interrupted(&interrupted)?;
+    let launched = {
+        let mut processes = context.processes.lock().await;
+        let process = processes
+            .get_mut(&name)
+            .ok_or(SetStateError::MetamodelError)?;
+
+        // already launched by another state
+        if process.is_active() {
+            false
+        } else {
+            process.restart_count = 0;
+            process.one_shot = one_shot;
+            launch(&context, &name, process)?;
+            true
+        }
+    };
+    if launched {
+        context.notify_process_state_changed();
+    }
 
-    if tokio::fs::metadata(&socket_path).await.is_ok() {
-        tokio::fs::remove_file(&socket_path).await?;
+    if one_shot {
+        wait_terminated(&context, &name, &interrupted).await?;
+    } else {
+        wait_running(&context, &name, &interrupted).await?;
     }
+    Ok(name)
+}
+
+async fn handle_command(context: &Arc<Context>, command: SmClientCommand) -> SmResponse {
+    match command {
+        SmClientCommand::GetInitialState => {
+            if !get_intial_state() {
+                SmResponse::GetInitialState(Err(InitialStateError::FailedInitializeInitialState))
+            } else {
+                SmResponse::GetInitialState(Ok(()))
+            }
+        }
+        SmClientCommand::SetState(fg_state) => {
+            let result = set_state(context, fg_state).await.map_err(|error| {
+                println!("set_state failed : {:?}", error);
+                // e.g. launch failure
+                error
+                    .downcast_ref::<SetStateError>()
+                    .cloned()
+                    .unwrap_or(SetStateError::Failed)
+            });
+            SmResponse::SetState(result)
+        }
+        SmClientCommand::GetExecutionError(function_group) => {
+            let result = if !context
+                .machine_manifest
+                .function_group_set
+                .contains_key(&function_group)
+            {
+                Err(GetExecutionErrorError::MetamodelError)
+            } else {
+                // the function group is in a defined state
+                context
+                    .execution_errors
+                    .lock()
+                    .await
+                    .get(&function_group)
+                    .cloned()
+                    .ok_or(GetExecutionErrorError::Failed)
+            };
+            SmResponse::GetExecutionError(result)
+        }
+        SmClientCommand::GetProcessStatus(name) => {
+            let result = context
+                .processes
+                .lock()
+                .await
+                .get(&name)
+                .map(|process| ProcessStatus {
+                    process: name.clone(),
+                    pid: process.pid,
+                    restart_count: process.restart_count,
+                })
+                .ok_or(GetProcessStatusError::MetamodelError);
+            SmResponse::GetProcessStatus(result)
+        }
+    }
+}
+
+/// requests of a connection are handled concurrently, e.g. a newer SetState cancels the older one
+/// ExecutionErrorEvents are pushed on the same connection
+async fn handle_connection(context: Arc<Context>, stream: UnixStream) -> Result<()> {
+    let (reader, writer) = stream.into_split();
+    let writer = Arc::new(Mutex::new(writer));
 
-    let listener = UnixListener::bind(&socket_path)?;
-    let (mut stream, _) = listener.accept().await?;
+    let forwarder = tokio::spawn(forward_execution_error_events(
+        context.subscribe_execution_error_events(),
+        writer.clone(),
+    ));
+    let result = handle_requests(context, reader, writer).await;
+    forwarder.abort();
 
-    let mut buffer = vec![0; 1024];
+    result
+}
+
+async fn handle_requests(
+    context: Arc<Context>,
+    mut reader: OwnedReadHalf,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+) -> Result<()> {
     loop {
-        match stream.read(&mut buffer).await {
-            Ok(len) => {
-                assert!(len > 0);
-                let request_command =
-                    bincode::deserialize::<SmClientCommand>(&buffer).unwrap();
-                match request_command {
-                    SmClientCommand::GetInitialState => {
-                        let response = if get_intial_state() == false {
-                            SmResponse::GetInitialState(Err(InitialStateError::FailedInitializeInitialState.into()))
-                        } else {
-                            SmResponse::GetInitialState(Ok(()))
-                        };
-                        let serialized_resonse = bincode::serialize(&response).unwrap();
-                        stream.write(&serialized_resonse).await.unwrap();
-                    }
-                    SmClientCommand::SetState(_fg_state) => {
-                        // FIXME
-                        //let _ = set_state(fg_state);
-                    }
+        let request = match read_frame::<_, SmRequest>(&mut reader).await {
+            Ok(request) => request,
+            // closed by the client
+            Err(FrameError::Closed) => return Ok(()),
+            Err(error) => return Err(error.into()),
+        };
+
+        let context = context.clone();
+        let writer = writer.clone();
+        tokio::spawn(async move {
+            let message = SmMessage::Response {
+                id: request.id,
+                response: handle_command(&context, request.command).await,
+            };
+            if let Err(error) = write_frame(&mut *writer.lock().await, &message).await {
+                println!("failed to respond to the state client : {:?}", error);
+            }
+        });
+    }
+}
+
+async fn forward_execution_error_events(
+    mut events: broadcast::Receiver<ExecutionErrorEvent>,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(count)) => {
+                println!("{} execution error events are dropped for a slow state client", count);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        let message = SmMessage::ExecutionErrorEvent(event);
+        if write_frame(&mut *writer.lock().await, &message).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Serve StateClient of SMs for the whole EM lifetime
+pub async fn state_receiver<P: AsRef<Path>>(context: Arc<Context>, socket_path: P) -> Result<()> {
+    let socket_path = socket_path.as_ref();
+    if tokio::fs::metadata(socket_path).await.is_ok() {
+        tokio::fs::remove_file(socket_path).await?;
+    }
+
+    let listener = UnixListener::bind(socket_path)?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let context = context.clone();
+        tokio::spawn(async move {
+            if let Err(error) = handle_connection(context, stream).await {
+                println!("state client connection error : {:?}", error);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::ExitStatus;
+    use crate::event::execution_manager::report_execution_state;
+    use crate::function_group_state::group::group;
+    use crate::machine_action::RecordingBackend;
+    use crate::resource_group::ResourceGroups;
+    use ara_exec::execution_client::OOM_EXECUTION_ERROR;
+    use ara_exec::manifest::machine_manifest::{FunctionGroupTransition, ResourceGroup};
+    use std::collections::HashMap;
+    use ara_exec::execution_client::ExecutionState;
+    use ara_exec::state_client::StateClient;
+    use std::sync::Arc;
+    use ara_exec::manifest::machine_manifest::MachineManifest;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+    use tokio::time::{sleep, Duration};
+
+    fn make_ro_oara_root(dir_name: &str) -> PathBuf {
+        let ro_oara_root = std::env::temp_dir().join(dir_name);
+        if ro_oara_root.exists() {
+            std::fs::remove_dir_all(&ro_oara_root).unwrap();
+        }
+        std::fs::create_dir_all(&ro_oara_root).unwrap();
+        ro_oara_root
+    }
+
+    fn install_executable<P: AsRef<Path>>(ro_oara_root: P, name: &str, script: &str) {
+        let path = ro_oara_root.as_ref().join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn make_context(ro_oara_root: &Path, execution_manifests: &[&str]) -> Arc<Context> {
+        make_context_with(ro_oara_root, execution_manifests, |context| context)
+    }
+
+    /// `configure` sets up e.g. the machine action backend
+    fn make_context_with<F>(
+        ro_oara_root: &Path,
+        execution_manifests: &[&str],
+        configure: F,
+    ) -> Arc<Context>
+    where
+        F: FnOnce(Context) -> Context,
+    {
+        let machine_manifest = MachineManifest::from(
+            r#"
+            environment_variable:
+              MACHINE_ENV: "machine"
+            function_group_set:
+              MachineFG:
+                initial_mode: "Startup"
+                mode:
+                  - "Startup"
+                  - "Shutdown"
+                  - "Restart"
+              FG1:
+                initial_mode: "Off"
+                mode:
+                  - "Off"
+                  - "On"
+        "#,
+        )
+        .unwrap();
+        Arc::new(configure(make_context_from(
+            machine_manifest,
+            ro_oara_root,
+            execution_manifests,
+        )))
+    }
+
+    fn make_context_from(
+        machine_manifest: MachineManifest,
+        ro_oara_root: &Path,
+        execution_manifests: &[&str],
+    ) -> Context {
+        let execution_manifests: Vec<_> = execution_manifests
+            .iter()
+            .map(|manifest| ExecutionManifest::from(manifest).unwrap())
+            .collect();
+        let fg_hashmap = group(&machine_manifest, &execution_manifests).unwrap();
+        Context::new(
+            machine_manifest,
+            &execution_manifests,
+            fg_hashmap,
+            ro_oara_root,
+        )
+    }
+
+    async fn kill_all(context: &Context) {
+        let children: Vec<_> = context
+            .processes
+            .lock()
+            .await
+            .values()
+            .filter_map(|process| process.child.clone())
+            .collect();
+        for child in children {
+            kill(&child).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn set_state_launch() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t1");
+        let output = ro_oara_root.join("APP1.out");
+        install_executable(
+            &ro_oara_root,
+            "APP1",
+            &format!("echo \"$@ $MACHINE_ENV $APP_ENV\" > {}\nexec sleep 10", output.display()),
+        );
+        install_executable(&ro_oara_root, "APP2", "exec sleep 10");
+
+        let context = make_context(
+            &ro_oara_root,
+            &[
+                r#"
+                name: APP2
+                app_dependency:
+                  - APP1.Running
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: APP1
+                environmental_variable:
+                  APP_ENV: "application"
+                argument:
+                  ARG1: "argument1"
+                mode_dependency:
+                  - FG1.On
+                "#,
+            ],
+        );
+
+        let fg_state = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        set_state(&context, fg_state).await.unwrap();
+
+        // Non-reporting processes
+        for process in context.processes.lock().await.values() {
+            assert_eq!(process.process_state, ProcessState::Running);
+            assert!(process.pid.is_some());
+        }
+
+        // wait for APP1 to write its arguments and environments
+        for _ in 0..100 {
+            if output.exists() && !std::fs::read_to_string(&output).unwrap().is_empty() {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "--ARG1=argument1 machine application\n"
+        );
+
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_unknown_state() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t2");
+        let context = make_context(&ro_oara_root, &[]);
+
+        for fg_state in [
+            FunctionGroupState::new("FG2".to_owned(), "On".to_owned()),
+            FunctionGroupState::new("FG1".to_owned(), "Verify".to_owned()),
+        ] {
+            let error = set_state(&context, fg_state).await.err().unwrap();
+            assert!(matches!(
+                error.downcast_ref::<SetStateError>().unwrap(),
+                SetStateError::MetamodelError
+            ));
+        }
+
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_terminate() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t3");
+        let stop_log = ro_oara_root.join("stop.log");
+        for name in ["APP1", "APP2"] {
+            install_executable(
+                &ro_oara_root,
+                name,
+                &format!(
+                    "trap 'echo {} >> {}; exit 0' TERM\nwhile true; do sleep 0.1; done",
+                    name,
+                    stop_log.display()
+                ),
+            );
+        }
+        // ignores SIGTERM
+        install_executable(
+            &ro_oara_root,
+            "APP3",
+            "trap '' TERM\nwhile true; do sleep 0.1; done",
+        );
+        install_executable(&ro_oara_root, "OFFAPP", "exec sleep 10");
+
+        let context = make_context(
+            &ro_oara_root,
+            &[
+                r#"
+                name: APP1
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: APP2
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: APP3
+                enter_exit_timeout:
+                  enter: 1
+                  exit: 0
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: OFFAPP
+                mode_dependency:
+                  - FG1.Off
+                "#,
+            ],
+        );
+
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        set_state(&context, on).await.unwrap();
+        // give the shells time to install the traps
+        sleep(Duration::from_millis(100)).await;
+
+        let off = FunctionGroupState::new("FG1".to_owned(), "Off".to_owned());
+        set_state(&context, off).await.unwrap();
+
+        {
+            let processes = context.processes.lock().await;
+            for name in ["APP1", "APP2", "APP3"] {
+                let process = processes.get(name).unwrap();
+                assert_eq!(process.process_state, ProcessState::Terminated);
+                assert!(process.pid.is_none());
+            }
+            assert_eq!(
+                processes.get("OFFAPP").unwrap().process_state,
+                ProcessState::Running
+            );
+        }
+        assert_eq!(
+            context.states.lock().await.get("FG1").unwrap(),
+            "Off"
+        );
+
+        // reversed order of the startup
+        assert_eq!(std::fs::read_to_string(&stop_log).unwrap(), "APP2\nAPP1\n");
+
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_wait_running() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t4");
+        install_executable(&ro_oara_root, "APP1", "exec sleep 10");
+        install_executable(&ro_oara_root, "APP2", "exec sleep 10");
+
+        let context = make_context(
+            &ro_oara_root,
+            &[
+                r#"
+                name: APP2
+                app_dependency:
+                  - APP1.Running
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: APP1
+                reporting_behavior: true
+                mode_dependency:
+                  - FG1.On
+                "#,
+            ],
+        );
+
+        let cloned_context = context.clone();
+        let handle = tokio::spawn(async move {
+            let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+            set_state(&cloned_context, on).await
+        });
+
+        context
+            .wait_process_state("APP1", ProcessState::Starting)
+            .await;
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            context.processes.lock().await.get("APP2").unwrap().process_state,
+            ProcessState::Idle
+        );
+        assert!(!handle.is_finished());
+
+        // APP1 reports Running, then APP2 is released
+        let pid = context.processes.lock().await.get("APP1").unwrap().pid.unwrap();
+        report_execution_state(&context, pid, ExecutionState::Running)
+            .await
+            .unwrap();
+        handle.await.unwrap().unwrap();
+        assert_eq!(
+            context.processes.lock().await.get("APP2").unwrap().process_state,
+            ProcessState::Running
+        );
+
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_enter_timeout() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t5");
+        // never reports Running, and has a child in its process group
+        install_executable(&ro_oara_root, "HANG", "sleep 100 &\nexec sleep 100");
+
+        let context = make_context(
+            &ro_oara_root,
+            &[r#"
+                name: HANG
+                reporting_behavior: true
+                enter_exit_timeout:
+                  enter: 1
+                  exit: 1
+                mode_dependency:
+                  - FG1.On
+                "#],
+        );
+
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        let error = set_state(&context, on).await.err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<SetStateError>().unwrap(),
+            SetStateError::Failed
+        ));
+
+        let (pid, state) = {
+            let processes = context.processes.lock().await;
+            let process = processes.get("HANG").unwrap();
+            (process.pid, process.process_state)
+        };
+        assert_eq!(state, ProcessState::Terminated);
+        assert!(pid.is_none());
+
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_terminated_on_enter() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t6");
+        install_executable(&ro_oara_root, "CRASH", "exit 1");
+
+        let context = make_context(
+            &ro_oara_root,
+            &[r#"
+                name: CRASH
+                reporting_behavior: true
+                mode_dependency:
+                  - FG1.On
+                "#],
+        );
+
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        let error = set_state(&context, on).await.err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<SetStateError>().unwrap(),
+            SetStateError::FailedUnexpectedTerminationOnEnter
+        ));
+        assert_eq!(
+            context.processes.lock().await.get("CRASH").unwrap().process_state,
+            ProcessState::Terminated
+        );
+
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_canceled() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t8");
+        install_executable(&ro_oara_root, "SLOW", "exec sleep 10");
+
+        let context = make_context(
+            &ro_oara_root,
+            &[r#"
+                name: SLOW
+                reporting_behavior: true
+                enter_exit_timeout:
+                  enter: 10
+                  exit: 1
+                mode_dependency:
+                  - FG1.On
+                "#],
+        );
+
+        let cloned_context = context.clone();
+        let older = tokio::spawn(async move {
+            let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+            set_state(&cloned_context, on).await
+        });
+        context
+            .wait_process_state("SLOW", ProcessState::Starting)
+            .await;
+
+        // the newer request cancels the older one blocked on SLOW, then stops SLOW
+        let off = FunctionGroupState::new("FG1".to_owned(), "Off".to_owned());
+        set_state(&context, off).await.unwrap();
+
+        let error = older.await.unwrap().err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<SetStateError>().unwrap(),
+            SetStateError::Canceled
+        ));
+        assert_eq!(
+            context.processes.lock().await.get("SLOW").unwrap().process_state,
+            ProcessState::Terminated
+        );
+        assert_eq!(context.states.lock().await.get("FG1").unwrap(), "Off");
+
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_unexpected_termination() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t11");
+        install_executable(&ro_oara_root, "CRASH", "sleep 0.2\nexit 1");
+        install_executable(&ro_oara_root, "SLOW", "exec sleep 10");
+
+        let context = make_context(
+            &ro_oara_root,
+            &[
+                r#"
+                name: SLOW
+                reporting_behavior: true
+                enter_exit_timeout:
+                  enter: 10
+                  exit: 1
+                app_dependency:
+                  - CRASH.Running
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: CRASH
+                number_of_restart: 1
+                mode_dependency:
+                  - FG1.On
+                "#,
+            ],
+        );
+
+        // CRASH terminates while the transition waits for SLOW
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        let error = set_state(&context, on).await.err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<SetStateError>().unwrap(),
+            SetStateError::FailedUnexpectedTermination
+        ));
+        {
+            let processes = context.processes.lock().await;
+            let process = processes.get("CRASH").unwrap();
+            assert_eq!(process.process_state, ProcessState::Terminated);
+            assert_eq!(process.exit_status, Some(ExitStatus::Exited(1)));
+            // the transition fails instead
+            assert_eq!(process.restart_count, 0);
+        }
+        assert_eq!(context.states.lock().await.get("FG1").unwrap(), "Off");
+
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    async fn request_set_state(
+        state_client: &StateClient,
+        fg: &str,
+        state: &str,
+    ) -> Result<(), SetStateError> {
+        let fg_state = FunctionGroupState::new(fg.to_owned(), state.to_owned());
+        state_client
+            .set_state(&fg_state)
+            .await
+            .map_err(|error| error.downcast::<SetStateError>().unwrap())
+    }
+
+    async fn create_state_client(socket_path: &Path) -> StateClient {
+        StateClient::builder()
+            .socket_path(socket_path)
+            .create(|_| {})
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn state_receiver_set_state() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t7");
+        let socket_path = ro_oara_root.join("sm_domain_socket");
+        install_executable(&ro_oara_root, "APP1", "exec sleep 10");
+        // no executable for APP2
+
+        let context = make_context(
+            &ro_oara_root,
+            &[
+                r#"
+                name: APP1
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: APP2
+                mode_dependency:
+                  - MachineFG.Restart
+                "#,
+            ],
+        );
+
+        let handle = tokio::spawn(state_receiver(context.clone(), socket_path.clone()));
+        // wait a second to create domain socket
+        sleep(Duration::from_millis(10)).await;
+
+        let mut clients = Vec::new();
+        for _ in 0..2 {
+            let socket_path = socket_path.clone();
+            clients.push(tokio::spawn(async move {
+                let state_client = create_state_client(&socket_path).await;
+                let mut responses = Vec::new();
+                for (fg, state) in [("FG2", "On"), ("FG1", "Verify"), ("MachineFG", "Off")] {
+                    responses.push(request_set_state(&state_client, fg, state).await);
                 }
+                responses
+            }));
+        }
+        for client in clients {
+            assert_eq!(
+                client.await.unwrap(),
+                vec![
+                    Err(SetStateError::MetamodelError),
+                    Err(SetStateError::MetamodelError),
+                    Err(SetStateError::InvalidTransition),
+                ]
+            );
+        }
+
+        let state_client = create_state_client(&socket_path).await;
+        assert_eq!(request_set_state(&state_client, "FG1", "On").await, Ok(()));
+        // launch failure
+        assert_eq!(
+            request_set_state(&state_client, "MachineFG", "Restart").await,
+            Err(SetStateError::Failed)
+        );
+
+        handle.abort();
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn state_receiver_execution_error_event() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t9");
+        let socket_path = ro_oara_root.join("sm_domain_socket");
+        install_executable(&ro_oara_root, "CRASH", "sleep 0.2\nexit 1");
+        install_executable(&ro_oara_root, "APP", "exec sleep 10");
+
+        let context = make_context(
+            &ro_oara_root,
+            &[
+                r#"
+                name: CRASH
+                execution_error: 7
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: APP
+                mode_dependency:
+                  - FG1.On
+                "#,
+            ],
+        );
+
+        let handle = tokio::spawn(state_receiver(context.clone(), socket_path.clone()));
+        // wait a second to create domain socket
+        sleep(Duration::from_millis(10)).await;
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let state_client = StateClient::builder()
+            .socket_path(&socket_path)
+            .create(move |event| sender.send(event).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(request_set_state(&state_client, "FG1", "On").await, Ok(()));
+
+        let event = tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let expected = ExecutionErrorEvent {
+            execution_error: 7,
+            function_group: "FG1".to_owned(),
+        };
+        assert_eq!(event, expected);
+        assert_eq!(
+            state_client.get_execution_error("FG1").await.unwrap(),
+            expected
+        );
+        {
+            let processes = context.processes.lock().await;
+            assert_eq!(
+                processes.get("CRASH").unwrap().process_state,
+                ProcessState::Terminated
+            );
+            assert_eq!(
+                processes.get("APP").unwrap().process_state,
+                ProcessState::Running
+            );
+        }
+
+        // a requested state defines the function group again
+        assert_eq!(request_set_state(&state_client, "FG1", "Off").await, Ok(()));
+        for (function_group, expected) in [
+            ("FG1", GetExecutionErrorError::Failed),
+            ("FG2", GetExecutionErrorError::MetamodelError),
+        ] {
+            let error = state_client
+                .get_execution_error(function_group)
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(
+                error.downcast_ref::<GetExecutionErrorError>().unwrap(),
+                &expected
+            );
+        }
+
+        handle.abort();
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn process_monitor_restart() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t10");
+        let launch_log = ro_oara_root.join("launch.log");
+        install_executable(
+            &ro_oara_root,
+            "CRASH",
+            &format!("echo launched >> {}\nsleep 0.1\nexit 1", launch_log.display()),
+        );
+
+        let context = make_context(
+            &ro_oara_root,
+            &[r#"
+                name: CRASH
+                number_of_restart: 2
+                restart_backoff: 50
+                mode_dependency:
+                  - FG1.On
+                "#],
+        );
+        let mut events = context.subscribe_execution_error_events();
+
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        set_state(&context, on).await.unwrap();
+
+        // escalated once the restarts are used up
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.function_group, "FG1");
+        assert_eq!(
+            std::fs::read_to_string(&launch_log).unwrap(),
+            "launched\nlaunched\nlaunched\n"
+        );
+        {
+            let processes = context.processes.lock().await;
+            let process = processes.get("CRASH").unwrap();
+            assert_eq!(process.restart_count, 2);
+            assert_eq!(process.process_state, ProcessState::Terminated);
+        }
+
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn state_receiver_process_status() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t20");
+        let socket_path = ro_oara_root.join("sm_domain_socket");
+        let marker = ro_oara_root.join("FLAKY.crashed");
+        // crashes on the first run only
+        install_executable(
+            &ro_oara_root,
+            "FLAKY",
+            &format!(
+                "if [ ! -f {0} ]; then touch {0}; sleep 0.1; exit 1; fi\nexec sleep 10",
+                marker.display()
+            ),
+        );
+
+        let context = make_context(
+            &ro_oara_root,
+            &[r#"
+                name: FLAKY
+                number_of_restart: 1
+                mode_dependency:
+                  - FG1.On
+                "#],
+        );
+
+        let handle = tokio::spawn(state_receiver(context.clone(), socket_path.clone()));
+        // wait a second to create domain socket
+        sleep(Duration::from_millis(10)).await;
+        let state_client = create_state_client(&socket_path).await;
+        assert_eq!(request_set_state(&state_client, "FG1", "On").await, Ok(()));
+
+        let mut status = state_client.get_process_status("FLAKY").await.unwrap();
+        for _ in 0..100 {
+            // relaunched after its backoff
+            if status.restart_count == 1 && status.pid.is_some() {
+                break;
             }
-            Err(error) => {
-                panic!("error on read with '{:?}'", error);
+            sleep(Duration::from_millis(10)).await;
+            status = state_client.get_process_status("FLAKY").await.unwrap();
+        }
+        assert_eq!(status.restart_count, 1);
+        assert!(status.pid.is_some());
+
+        // a successful SetState renews the restart budget
+        assert_eq!(request_set_state(&state_client, "FG1", "On").await, Ok(()));
+        let status = state_client.get_process_status("FLAKY").await.unwrap();
+        assert_eq!(status.restart_count, 0);
+
+        let error = state_client.get_process_status("NONE").await.err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<GetProcessStatusError>().unwrap(),
+            &GetProcessStatusError::MetamodelError
+        );
+
+        handle.abort();
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_machine_shutdown() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t12");
+        let stop_log = ro_oara_root.join("stop.log");
+        for name in ["APP1", "APP2", "SYS"] {
+            install_executable(
+                &ro_oara_root,
+                name,
+                &format!(
+                    "trap 'echo {} >> {}; exit 0' TERM\nwhile true; do sleep 0.1; done",
+                    name,
+                    stop_log.display()
+                ),
+            );
+        }
+        install_executable(&ro_oara_root, "PERSISTENCY", "exec sleep 10");
+
+        let backend = Arc::new(RecordingBackend::default());
+        let context = make_context_with(
+            &ro_oara_root,
+            &[
+                r#"
+                name: SYS
+                mode_dependency:
+                  - MachineFG.Startup
+                "#,
+                r#"
+                name: PERSISTENCY
+                mode_dependency:
+                  - FG1.On
+                  - MachineFG.Shutdown
+                "#,
+                r#"
+                name: APP2
+                app_dependency:
+                  - APP1.Running
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: APP1
+                mode_dependency:
+                  - FG1.On
+                "#,
+            ],
+            |context| context.with_machine_action_backend(backend.clone()),
+        );
+
+        let startup = FunctionGroupState::new(MACHINE_FG.to_owned(), "Startup".to_owned());
+        set_state(&context, startup).await.unwrap();
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        set_state(&context, on).await.unwrap();
+        // give the shells time to install the traps
+        sleep(Duration::from_millis(100)).await;
+        let persistency_pid = context.processes.lock().await.get("PERSISTENCY").unwrap().pid;
+
+        let shutdown = FunctionGroupState::new(MACHINE_FG.to_owned(), "Shutdown".to_owned());
+        set_state(&context, shutdown).await.unwrap();
+
+        // the other function groups in the reversed order first, then MachineFG
+        assert_eq!(
+            std::fs::read_to_string(&stop_log).unwrap(),
+            "APP2\nAPP1\nSYS\n"
+        );
+        {
+            let processes = context.processes.lock().await;
+            for name in ["APP1", "APP2", "SYS"] {
+                assert_eq!(
+                    processes.get(name).unwrap().process_state,
+                    ProcessState::Terminated
+                );
             }
+            // kept as it belongs to Shutdown
+            assert_eq!(processes.get("PERSISTENCY").unwrap().pid, persistency_pid);
         }
-        break;
+        {
+            let states = context.states.lock().await;
+            assert_eq!(states.get("FG1").unwrap(), "Off");
+            assert_eq!(states.get(MACHINE_FG).unwrap(), "Shutdown");
+        }
+        assert_eq!(backend.actions(), vec![MachineAction::Shutdown]);
+
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn stop_all_processes() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t13");
+        install_executable(&ro_oara_root, "SYS", "exec sleep 10");
+        install_executable(&ro_oara_root, "APP1", "exec sleep 10");
+        // ignores SIGTERM
+        install_executable(
+            &ro_oara_root,
+            "APP2",
+            "trap '' TERM\nwhile true; do sleep 0.1; done",
+        );
+
+        let context = make_context(
+            &ro_oara_root,
+            &[
+                r#"
+                name: SYS
+                mode_dependency:
+                  - MachineFG.Startup
+                "#,
+                r#"
+                name: APP1
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: APP2
+                enter_exit_timeout:
+                  enter: 1
+                  exit: 0
+                mode_dependency:
+                  - FG1.On
+                "#,
+            ],
+        );
+
+        let startup = FunctionGroupState::new(MACHINE_FG.to_owned(), "Startup".to_owned());
+        set_state(&context, startup).await.unwrap();
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        set_state(&context, on.clone()).await.unwrap();
+        // give the shell time to install the trap
+        sleep(Duration::from_millis(100)).await;
+
+        // APP2 had to be killed
+        assert!(!stop_all(&context).await);
+        for process in context.processes.lock().await.values() {
+            assert_eq!(process.process_state, ProcessState::Terminated);
+            assert!(process.pid.is_none());
+        }
+        assert_eq!(context.states.lock().await.get("FG1").unwrap(), "Off");
+
+        // every process exits on SIGTERM
+        install_executable(&ro_oara_root, "SLOW", "exec sleep 10");
+        let context = make_context(
+            &ro_oara_root,
+            &[
+                r#"
+                name: APP1
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: SLOW
+                reporting_behavior: true
+                app_dependency:
+                  - APP1.Running
+                enter_exit_timeout:
+                  enter: 10
+                  exit: 1
+                mode_dependency:
+                  - FG1.On
+                "#,
+            ],
+        );
+        let cloned_context = context.clone();
+        let cloned_on = on.clone();
+        let in_flight = tokio::spawn(async move { set_state(&cloned_context, cloned_on).await });
+        context
+            .wait_process_state("SLOW", ProcessState::Starting)
+            .await;
+
+        // the in-flight SetState is canceled, and SetState of a SM still connected doesn't
+        // launch anything again
+        assert!(stop_all(&context).await);
+        let error = in_flight.await.unwrap().err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<SetStateError>().unwrap(),
+            SetStateError::Canceled
+        ));
+        let error = set_state(&context, on).await.err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<SetStateError>().unwrap(),
+            SetStateError::Canceled
+        ));
+        for process in context.processes.lock().await.values() {
+            assert_eq!(process.process_state, ProcessState::Terminated);
+            assert!(process.pid.is_none());
+        }
+
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stop_all_dependents_first() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t21");
+        let stop_log = ro_oara_root.join("stop.log");
+        for name in ["SRV", "GUI"] {
+            install_executable(
+                &ro_oara_root,
+                name,
+                &format!(
+                    "trap 'echo {} >> {}; exit 0' TERM\nwhile true; do sleep 0.1; done",
+                    name,
+                    stop_log.display()
+                ),
+            );
+        }
+        // Display depends on Base although it comes after it by name
+        let machine_manifest = MachineManifest::from(
+            r#"
+            function_group_set:
+              MachineFG:
+                initial_mode: "Startup"
+                mode:
+                  - "Startup"
+                  - "Shutdown"
+                  - "Restart"
+              Base:
+                initial_mode: "Off"
+                mode:
+                  - "Off"
+                  - "On"
+              Display:
+                initial_mode: "Off"
+                mode:
+                  - "Off"
+                  - "On"
+        "#,
+        )
+        .unwrap();
+        let execution_manifests: Vec<_> = [
+            r#"
+            name: SRV
+            mode_dependency:
+              - Base.On
+              - Display.On
+            "#,
+            r#"
+            name: GUI
+            app_dependency:
+              - SRV.Running
+            mode_dependency:
+              - Display.On
+            "#,
+        ]
+        .iter()
+        .map(|manifest| ExecutionManifest::from(manifest).unwrap())
+        .collect();
+        let fg_hashmap = group(&machine_manifest, &execution_manifests).unwrap();
+        let context = Arc::new(Context::new(
+            machine_manifest,
+            &execution_manifests,
+            fg_hashmap,
+            &ro_oara_root,
+        ));
+
+        assert_eq!(stop_order(&context).await, ["Display", "Base"]);
+
+        for function_group in ["Base", "Display"] {
+            let on = FunctionGroupState::new(function_group.to_owned(), "On".to_owned());
+            set_state(&context, on).await.unwrap();
+        }
+        // give the shells time to install the traps
+        sleep(Duration::from_millis(100)).await;
+
+        assert!(stop_all(&context).await);
+        // GUI is stopped with Display before SRV of Base could go away
+        assert_eq!(std::fs::read_to_string(&stop_log).unwrap(), "GUI\nSRV\n");
+
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn process_monitor_oom_kill() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t14");
+        install_executable(&ro_oara_root, "HOG", "exec sleep 10");
+
+        // a fake cgroup hierarchy, the kernel would count OOM kills in memory.events
+        let cgroup_root = ro_oara_root.join("cgroup");
+        let mut resource_group = HashMap::new();
+        resource_group.insert("RG1".to_owned(), ResourceGroup::default());
+        let resource_groups = ResourceGroups::create(&cgroup_root, &resource_group).unwrap();
+        std::fs::write(cgroup_root.join("RG1/cgroup.procs"), "").unwrap();
+
+        let context = make_context_with(
+            &ro_oara_root,
+            &[r#"
+                name: HOG
+                resource_group: RG1
+                execution_error: 3
+                mode_dependency:
+                  - FG1.On
+                "#],
+            |context| context.with_resource_groups(resource_groups),
+        );
+        let mut events = context.subscribe_execution_error_events();
+
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        set_state(&context, on).await.unwrap();
+        // joined its resource group before exec
+        assert_eq!(
+            std::fs::read_to_string(cgroup_root.join("RG1/cgroup.procs")).unwrap(),
+            "0"
+        );
+
+        std::fs::write(cgroup_root.join("RG1/memory.events"), "oom 1\noom_kill 1\n").unwrap();
+        kill_all(&context).await;
+
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.function_group, "FG1");
+        assert_eq!(event.execution_error, OOM_EXECUTION_ERROR);
+        assert_eq!(
+            context.processes.lock().await.get("HOG").unwrap().exit_status,
+            Some(ExitStatus::OomKilled)
+        );
+
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_log_file() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t15");
+        install_executable(
+            &ro_oara_root,
+            "APP1",
+            "echo out\necho err >&2\nexec sleep 10",
+        );
+
+        let rw_oara_root = ro_oara_root.join("rw");
+        let context = make_context_with(
+            &ro_oara_root,
+            &[r#"
+                name: APP1
+                log:
+                  output: file
+                mode_dependency:
+                  - FG1.On
+                "#],
+            |context| context.with_rw_oara_root(&rw_oara_root),
+        );
+
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        set_state(&context, on).await.unwrap();
+
+        let log_file = rw_oara_root.join("APP1/log/APP1.log");
+        let mut lines = Vec::new();
+        for _ in 0..100 {
+            if let Ok(log) = std::fs::read_to_string(&log_file) {
+                lines = log.lines().map(str::to_owned).collect();
+                if lines.len() == 2 {
+                    break;
+                }
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        lines.sort();
+        assert_eq!(lines, vec!["err", "out"]);
+
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_parallel_start() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t16");
+        for name in ["APP1", "APP2", "APP3", "APP4"] {
+            install_executable(&ro_oara_root, name, "exec sleep 10");
+        }
+
+        let context = make_context_with(
+            &ro_oara_root,
+            &[
+                r#"
+                name: APP1
+                reporting_behavior: true
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: APP2
+                reporting_behavior: true
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: APP3
+                app_dependency:
+                  - APP2.Running
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: APP4
+                mode_dependency:
+                  - FG1.On
+                "#,
+            ],
+            |context| context.with_max_parallel_start(2.try_into().unwrap()),
+        );
+
+        let cloned_context = context.clone();
+        let handle = tokio::spawn(async move {
+            let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+            set_state(&cloned_context, on).await
+        });
+
+        // APP1 and APP2 start together, APP4 waits for a free slot
+        context
+            .wait_process_state("APP1", ProcessState::Starting)
+            .await;
+        context
+            .wait_process_state("APP2", ProcessState::Starting)
+            .await;
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(
+            context.processes.lock().await.get("APP4").unwrap().process_state,
+            ProcessState::Idle
+        );
+
+        // APP4 and APP3 are released by APP2 while APP1 is still starting
+        let pid = context.processes.lock().await.get("APP2").unwrap().pid.unwrap();
+        report_execution_state(&context, pid, ExecutionState::Running)
+            .await
+            .unwrap();
+        context
+            .wait_process_state("APP3", ProcessState::Running)
+            .await;
+        context
+            .wait_process_state("APP4", ProcessState::Running)
+            .await;
+        assert_eq!(
+            context.processes.lock().await.get("APP1").unwrap().process_state,
+            ProcessState::Starting
+        );
+        assert!(!handle.is_finished());
+
+        let pid = context.processes.lock().await.get("APP1").unwrap().pid.unwrap();
+        report_execution_state(&context, pid, ExecutionState::Running)
+            .await
+            .unwrap();
+        handle.await.unwrap().unwrap();
+
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_one_shot() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t17");
+        let marker = ro_oara_root.join("SETUP.done");
+        let output = ro_oara_root.join("APP1.out");
+        install_executable(
+            &ro_oara_root,
+            "SETUP",
+            &format!("sleep 0.2\ntouch {}", marker.display()),
+        );
+        install_executable(
+            &ro_oara_root,
+            "APP1",
+            &format!(
+                "test -f {} && echo done > {}\nexec sleep 10",
+                marker.display(),
+                output.display()
+            ),
+        );
+        install_executable(&ro_oara_root, "FAIL", "exit 3");
+        install_executable(&ro_oara_root, "APP2", "exec sleep 10");
+
+        let manifests = [
+            r#"
+            name: APP1
+            app_dependency:
+              - SETUP.Terminated
+            mode_dependency:
+              - FG1.On
+            "#,
+            r#"
+            name: SETUP
+            mode_dependency:
+              - FG1.On
+            "#,
+        ];
+        let context = make_context(&ro_oara_root, &manifests);
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        set_state(&context, on.clone()).await.unwrap();
+        {
+            let processes = context.processes.lock().await;
+            let setup = processes.get("SETUP").unwrap();
+            assert_eq!(setup.process_state, ProcessState::Terminated);
+            assert_eq!(setup.exit_status, Some(ExitStatus::Exited(0)));
+            assert_eq!(
+                processes.get("APP1").unwrap().process_state,
+                ProcessState::Running
+            );
+        }
+        // APP1 is launched after SETUP exited
+        for _ in 0..100 {
+            if output.exists() {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        assert!(output.exists());
+        // the exit of a one-shot process is not supervised
+        assert!(context.execution_errors.lock().await.is_empty());
+        kill_all(&context).await;
+
+        // a failed one-shot process fails the transition, and its dependents are not launched
+        let context = make_context(
+            &ro_oara_root,
+            &[
+                r#"
+                name: APP2
+                app_dependency:
+                  - FAIL.Terminated
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: FAIL
+                mode_dependency:
+                  - FG1.On
+                "#,
+            ],
+        );
+        let error = set_state(&context, on).await.err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "One-shot process FAIL didn't exit successfully : Some(Exited(3))"
+        );
+        assert_eq!(
+            context.processes.lock().await.get("APP2").unwrap().process_state,
+            ProcessState::Idle
+        );
+        assert_eq!(context.states.lock().await.get("FG1").unwrap(), "Off");
+
+        // a one-shot process of FG1.On is long-running in MachineFG.Startup, and supervised there
+        install_executable(&ro_oara_root, "DAEMON", "exec sleep 10");
+        let context = make_context(
+            &ro_oara_root,
+            &[
+                r#"
+                name: APP2
+                app_dependency:
+                  - DAEMON.Terminated
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: DAEMON
+                enter_exit_timeout:
+                  enter: 1
+                  exit: 1
+                mode_dependency:
+                  - MachineFG.Startup
+                  - FG1.On
+                "#,
+            ],
+        );
+        let mut events = context.subscribe_execution_error_events();
+        let startup = FunctionGroupState::new(MACHINE_FG.to_owned(), "Startup".to_owned());
+        set_state(&context, startup).await.unwrap();
+        assert_eq!(
+            context.processes.lock().await.get("DAEMON").unwrap().process_state,
+            ProcessState::Running
+        );
+
+        kill_all(&context).await;
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.function_group, MACHINE_FG);
+
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_one_shot_relaunch() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t22");
+        let marker = ro_oara_root.join("DAEMON.once");
+        // long-running until the marker exists
+        install_executable(
+            &ro_oara_root,
+            "DAEMON",
+            &format!("test -f {} && exit 0\nexec sleep 10", marker.display()),
+        );
+        install_executable(&ro_oara_root, "APP2", "exec sleep 10");
+        let context = make_context(
+            &ro_oara_root,
+            &[
+                r#"
+                name: APP2
+                app_dependency:
+                  - DAEMON.Terminated
+                mode_dependency:
+                  - FG1.On
+                "#,
+                r#"
+                name: DAEMON
+                enter_exit_timeout:
+                  enter: 1
+                  exit: 1
+                mode_dependency:
+                  - MachineFG.Startup
+                  - FG1.On
+                "#,
+            ],
+        );
+        let startup = FunctionGroupState::new(MACHINE_FG.to_owned(), "Startup".to_owned());
+        set_state(&context, startup).await.unwrap();
+        let pid = context.processes.lock().await.get("DAEMON").unwrap().pid;
+        // past the enter timeout of the launch for Startup
+        sleep(Duration::from_millis(1200)).await;
+        std::fs::write(&marker, "").unwrap();
+
+        // DAEMON is launched again for FG1.On, and runs to its end there
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        set_state(&context, on).await.unwrap();
+        {
+            let processes = context.processes.lock().await;
+            let daemon = processes.get("DAEMON").unwrap();
+            assert_eq!(daemon.process_state, ProcessState::Terminated);
+            assert_eq!(daemon.exit_status, Some(ExitStatus::Exited(0)));
+            assert_ne!(daemon.pid, pid);
+            assert_eq!(
+                processes.get("APP2").unwrap().process_state,
+                ProcessState::Running
+            );
+        }
+        assert!(context.execution_errors.lock().await.is_empty());
+
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_invalid_transition() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t18");
+        install_executable(&ro_oara_root, "APP1", "exec sleep 10");
+
+        // only Off -> On
+        let context = make_context_with(
+            &ro_oara_root,
+            &[r#"
+                name: APP1
+                mode_dependency:
+                  - FG1.On
+                "#],
+            |mut context| {
+                let fg1 = context.machine_manifest.function_group_set.get_mut("FG1").unwrap();
+                fg1.transition = Some(vec![FunctionGroupTransition {
+                    from: OFF.to_owned(),
+                    to: "On".to_owned(),
+                }]);
+                context
+            },
+        );
+
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        set_state(&context, on.clone()).await.unwrap();
+        // staying in the same state
+        set_state(&context, on).await.unwrap();
+
+        let off = FunctionGroupState::new("FG1".to_owned(), OFF.to_owned());
+        let error = set_state(&context, off).await.err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<SetStateError>().unwrap(),
+            SetStateError::InvalidTransition
+        ));
+        // nothing is touched
+        assert_eq!(context.states.lock().await.get("FG1").unwrap(), "On");
+        assert_eq!(
+            context.processes.lock().await.get("APP1").unwrap().process_state,
+            ProcessState::Running
+        );
+
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state_concurrent_transition() {
+        let ro_oara_root = make_ro_oara_root("state_manager-t19");
+        install_executable(&ro_oara_root, "SLOW", "exec sleep 10");
+
+        // only Off -> On, nothing goes to Diag
+        let machine_manifest = MachineManifest::from(
+            r#"
+            function_group_set:
+              MachineFG:
+                initial_mode: "Startup"
+                mode:
+                  - "Startup"
+                  - "Shutdown"
+                  - "Restart"
+              FG1:
+                initial_mode: "Off"
+                mode:
+                  - "Off"
+                  - "On"
+                  - "Diag"
+                transition:
+                  - from: "Off"
+                    to: "On"
+        "#,
+        )
+        .unwrap();
+        let context = Arc::new(make_context_from(
+            machine_manifest,
+            &ro_oara_root,
+            &[r#"
+                name: SLOW
+                reporting_behavior: true
+                enter_exit_timeout:
+                  enter: 10
+                  exit: 1
+                mode_dependency:
+                  - FG1.On
+                "#],
+        ));
+        let on = FunctionGroupState::new("FG1".to_owned(), "On".to_owned());
+        let off = FunctionGroupState::new("FG1".to_owned(), OFF.to_owned());
+        let diag = FunctionGroupState::new("FG1".to_owned(), "Diag".to_owned());
+
+        // Off cancels the in-flight On, and goes from Off
+        let cloned_context = context.clone();
+        let cloned_on = on.clone();
+        let older = tokio::spawn(async move { set_state(&cloned_context, cloned_on).await });
+        context
+            .wait_process_state("SLOW", ProcessState::Starting)
+            .await;
+        set_state(&context, off).await.unwrap();
+        let error = older.await.unwrap().err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<SetStateError>().unwrap(),
+            SetStateError::Canceled
+        ));
+        assert_eq!(context.states.lock().await.get("FG1").unwrap(), OFF);
+
+        // Diag is allowed neither from Off nor from On, the in-flight On goes on
+        let cloned_context = context.clone();
+        let in_flight = tokio::spawn(async move { set_state(&cloned_context, on).await });
+        context
+            .wait_process_state("SLOW", ProcessState::Starting)
+            .await;
+        let error = set_state(&context, diag).await.err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<SetStateError>().unwrap(),
+            SetStateError::InvalidTransition
+        ));
+        assert_eq!(context.transition_target("FG1").unwrap(), "On");
+
+        let pid = context.processes.lock().await.get("SLOW").unwrap().pid.unwrap();
+        report_execution_state(&context, pid, ExecutionState::Running)
+            .await
+            .unwrap();
+        in_flight.await.unwrap().unwrap();
+        assert_eq!(context.states.lock().await.get("FG1").unwrap(), "On");
+
+        kill_all(&context).await;
+        std::fs::remove_dir_all(&ro_oara_root).unwrap();
+    }
 }
@@ -6,15 +6,17 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 enum GroupingError {
-    #[error("Not in the same mode for {0}")]
-    NotInTheSameMode(String),
-    #[error("Not dependency application : {0} for {1}")]
-    NoDepdencyApps(String, String),
+    #[error("Invalid mode dependency : {0} for {1}")]
+    InvalidModeDependency(String, String),
+    #[error("Dependency application {0} of {1} is not in {2}")]
+    MissingDependencyApp(String, String, String),
+    #[error("Circular dependency in {0} : {1}")]
+    CircularDependency(String, String),
 }
 
 // grouping manifest based on dependency
 
-pub type FunctionGroupStateHashMap = HashMap<String, Vec<ExecutionManifest>>;
+pub type FunctionGroupStateHashMap = HashMap<String, DependencyGraph>;
 pub type FunctionGroupHashMap = HashMap<String, FunctionGroupStateHashMap>;
 
 /*pub struct InternalFgMode {
@@ -22,14 +24,141 @@ pub type FunctionGroupHashMap = HashMap<String, FunctionGroupStateHashMap>;
     pub state: String,
 }*/
 
+/// app_dependency graph of the processes of a function group state
+///
+/// Processes are sorted topologically, a process comes after every process it depends on.
+/// Processes of the same layer don't depend on each other, and every dependency of a layer is
+/// in a former layer.
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    // start order
+    manifests: Vec<ExecutionManifest>,
+    // number of processes of every layer, in `manifests` order
+    layers: Vec<usize>,
+}
+
+impl DependencyGraph {
+    /// `fg_state` names the function group state in errors, e.g. "FG1.On"
+    /// Processes of a layer keep the order of `manifests`.
+    pub fn new(fg_state: &str, manifests: Vec<ExecutionManifest>) -> Result<Self> {
+        let index: HashMap<&str, usize> = manifests
+            .iter()
+            .enumerate()
+            .map(|(i, manifest)| (manifest.name.as_str(), i))
+            .collect();
+
+        // dependencies[i] : processes which `i` depends on
+        let mut dependencies = Vec::with_capacity(manifests.len());
+        for manifest in &manifests {
+            let mut depends_on = Vec::new();
+            for dependency in &manifest.app_dependency {
+                let app = dependency.app.as_str();
+                match index.get(app) {
+                    Some(&i) if !depends_on.contains(&i) => depends_on.push(i),
+                    Some(_) => {}
+                    None => {
+                        return Err(GroupingError::MissingDependencyApp(
+                            app.to_owned(),
+                            manifest.name.clone(),
+                            fg_state.to_owned(),
+                        )
+                        .into())
+                    }
+                }
+            }
+            dependencies.push(depends_on);
+        }
+
+        // Kahn's algorithm, a layer at a time
+        let mut dependents = vec![Vec::new(); manifests.len()];
+        for (i, depends_on) in dependencies.iter().enumerate() {
+            for &dependency in depends_on {
+                dependents[dependency].push(i);
+            }
+        }
+        let mut pending: Vec<usize> = dependencies.iter().map(Vec::len).collect();
+        let mut layer: Vec<usize> = (0..manifests.len()).filter(|&i| pending[i] == 0).collect();
+        let mut order = Vec::with_capacity(manifests.len());
+        let mut layers = Vec::new();
+        while !layer.is_empty() {
+            let mut next = Vec::new();
+            for &i in &layer {
+                for &dependent in &dependents[i] {
+                    pending[dependent] -= 1;
+                    if pending[dependent] == 0 {
+                        next.push(dependent);
+                    }
+                }
+            }
+            next.sort_unstable();
+            layers.push(layer.len());
+            order.append(&mut layer);
+            layer = next;
+        }
+
+        if order.len() < manifests.len() {
+            let cycle = find_cycle(&dependencies, &pending)
+                .into_iter()
+                .map(|i| manifests[i].name.as_str())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(GroupingError::CircularDependency(fg_state.to_owned(), cycle).into());
+        }
+
+        let mut manifests: Vec<Option<ExecutionManifest>> =
+            manifests.into_iter().map(Some).collect();
+        let manifests = order
+            .into_iter()
+            .map(|i| manifests[i].take().unwrap())
+            .collect();
+
+        Ok(Self { manifests, layers })
+    }
+
+    /// every process in the start order
+    pub fn manifests(&self) -> &[ExecutionManifest] {
+        &self.manifests
+    }
+
+    /// processes independent of each other, in the start order
+    pub fn layers(&self) -> Vec<&[ExecutionManifest]> {
+        let mut rest = self.manifests.as_slice();
+        self.layers
+            .iter()
+            .map(|&len| {
+                let (layer, next) = rest.split_at(len);
+                rest = next;
+                layer
+            })
+            .collect()
+    }
+}
+
+/// a cycle among the processes left by Kahn's algorithm, the first process is repeated at the end
+/// Every process left depends on another one left, so following them ends up in a cycle.
+fn find_cycle(dependencies: &[Vec<usize>], pending: &[usize]) -> Vec<usize> {
+    let mut path: Vec<usize> = Vec::new();
+    let mut current = (0..pending.len()).find(|&i| pending[i] > 0).unwrap();
+    loop {
+        if let Some(start) = path.iter().position(|&i| i == current) {
+            let mut cycle = path.split_off(start);
+            cycle.push(current);
+            return cycle;
+        }
+        path.push(current);
+        current = *dependencies[current]
+            .iter()
+            .find(|&&dependency| pending[dependency] > 0)
+            .unwrap();
+    }
+}
+
 // TBD : MachineFg도 Off를 넣어야 한다.
 // grouping manifest base on function group state
 pub fn group(
-    machine_manifest: MachineManifest,
-    execution_manifests: Vec<ExecutionManifest>,
+    machine_manifest: &MachineManifest,
+    execution_manifests: &[ExecutionManifest],
 ) -> Result<FunctionGroupHashMap> {
-    let mut function_group = FunctionGroupHashMap::new();
-
     // MachineFG
     //  |- Startup
     //  |       |- "App a"
@@ -39,82 +168,42 @@ pub fn group(
     //  |- Shutfown
     //  \- Off
 
+    let mut manifest_lists: HashMap<String, HashMap<String, Vec<ExecutionManifest>>> =
+        HashMap::new();
     for (fg_name, fg_states) in &machine_manifest.function_group_set {
         let mut mode = HashMap::new();
         for fg_state in &fg_states.mode {
             mode.insert(fg_state.clone(), Vec::new());
         }
-        function_group.insert(fg_name.clone(), mode);
+        manifest_lists.insert(fg_name.clone(), mode);
     }
 
     // collect all manifest
-    for manifest in &execution_manifests {
+    for manifest in execution_manifests {
         for dependency in &manifest.mode_dependency {
-            let (group_name, mode_name) = dependency.split_once('.').unwrap();
-            let group_name = group_name.to_owned();
-            let mode_name = mode_name.to_owned();
-
-            let mode = function_group.get_mut(&group_name).unwrap();
-            let manifest_list = mode.get_mut(&mode_name).unwrap();
+            let manifest_list = manifest_lists
+                .get_mut(&dependency.function_group)
+                .and_then(|mode| mode.get_mut(&dependency.state))
+                .ok_or_else(|| {
+                    GroupingError::InvalidModeDependency(
+                        dependency.to_string(),
+                        manifest.name.clone(),
+                    )
+                })?;
             manifest_list.push(manifest.clone());
         }
     }
 
     // prioritize by app dependenies
-
-    for (_, mode) in &mut function_group {
+    let mut function_group = FunctionGroupHashMap::new();
+    for (fg_name, mode) in manifest_lists {
+        let mut state_hashmap = FunctionGroupStateHashMap::new();
         for (mode_name, manifest_list) in mode {
-            let mut index = 0;
-            for _ in index..manifest_list.len() {
-                let app_name = &manifest_list[index].name;
-                if !manifest_list[index].app_dependency.is_empty() {
-                    let mut depend_index = None;
-                    for dependency in &manifest_list[index].app_dependency {
-                        let mut next_index = index + 1;
-                        let (depend_app, _) = dependency.split_once('.').unwrap();
-                        for _ in next_index..manifest_list.len() {
-                            if depend_app == manifest_list[next_index].name {
-                                // depdency application should in the same state
-                                let depend_mode = format!("{}.", mode_name);
-                                if !manifest_list[next_index]
-                                    .mode_dependency
-                                    .iter()
-                                    .any(|s| s.contains(&depend_mode))
-                                {
-                                    return Err(
-                                        GroupingError::NotInTheSameMode(app_name.clone()).into()
-                                    );
-                                }
-                                depend_index = Some(next_index);
-                            }
-                            next_index += 1;
-                        }
-                    }
-
-                    if let Some(di) = depend_index {
-                        // move `index` value next to `di` poistion
-                        let this_manifest = manifest_list.remove(index);
-                        manifest_list.insert(di, this_manifest);
-                        continue;
-                    } else {
-                        let dependency_apps = manifest_list[index].app_dependency.join(",");
-                        return Err(GroupingError::NoDepdencyApps(
-                            app_name.clone(),
-                            dependency_apps,
-                        )
-                        .into());
-                    }
-                }
-
-                index += 1;
-            }
-
-            if mode_name == "Off" {
-                // FIXME : to const variable
-                // reverse
-                manifest_list.reverse();
-            }
+            let fg_state = format!("{}.{}", fg_name, mode_name);
+            let graph = DependencyGraph::new(&fg_state, manifest_list)?;
+            state_hashmap.insert(mode_name, graph);
         }
+        function_group.insert(fg_name, state_hashmap);
     }
 
     Ok(function_group)
@@ -134,7 +223,157 @@ pub fn group(
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use ara_exec::manifest::dependency::{AppDependency, ModeDependency};
+
+    fn manifest(name: &str, app_dependency: &[&str]) -> ExecutionManifest {
+        let mut manifest = ExecutionManifest::from(&format!("name: {}", name)).unwrap();
+        manifest.app_dependency = app_dependency
+            .iter()
+            .map(|app| AppDependency::new(*app, "Running"))
+            .collect();
+        manifest.mode_dependency = vec![ModeDependency::new("FG1", "On")];
+        manifest
+    }
+
+    fn layer_names(graph: &DependencyGraph) -> Vec<Vec<&str>> {
+        graph
+            .layers()
+            .into_iter()
+            .map(|layer| {
+                layer
+                    .iter()
+                    .map(|manifest| manifest.name.as_str())
+                    .collect()
+            })
+            .collect()
+    }
 
     #[test]
-    fn grouping_test() {}
+    fn grouping_test() {
+        let machine_manifest = MachineManifest::from(
+            r#"
+            function_group_set:
+              MachineFG:
+                initial_mode: "Startup"
+                mode:
+                  - "Startup"
+                  - "Shutdown"
+                  - "Restart"
+              FG1:
+                initial_mode: "Off"
+                mode:
+                  - "Off"
+                  - "On"
+            "#,
+        )
+        .unwrap();
+        // the dependent is listed after its dependency
+        let manifests = vec![manifest("B", &[]), manifest("A", &["B"])];
+        let fg_hashmap = group(&machine_manifest, &manifests).unwrap();
+
+        let names = |state: &str| -> Vec<String> {
+            fg_hashmap["FG1"][state]
+                .manifests()
+                .iter()
+                .map(|manifest| manifest.name.clone())
+                .collect()
+        };
+        assert_eq!(names("On"), vec!["B", "A"]);
+        assert!(names("Off").is_empty());
+
+        // unknown function group state
+        let mut manifests = vec![manifest("A", &[])];
+        manifests[0].mode_dependency = vec![ModeDependency::new("FG2", "On")];
+        assert_eq!(
+            group(&machine_manifest, &manifests)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Invalid mode dependency : FG2.On for A"
+        );
+    }
+
+    #[test]
+    fn layers() {
+        // A -> B -> C, C first
+        let graph = DependencyGraph::new(
+            "FG1.On",
+            vec![
+                manifest("A", &["B"]),
+                manifest("B", &["C"]),
+                manifest("C", &[]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(layer_names(&graph), vec![vec!["C"], vec!["B"], vec!["A"]]);
+
+        // A -> C, B -> C
+        let graph = DependencyGraph::new(
+            "FG1.On",
+            vec![
+                manifest("A", &["C"]),
+                manifest("B", &["C"]),
+                manifest("C", &[]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(layer_names(&graph), vec![vec!["C"], vec!["A", "B"]]);
+
+        // A -> B -> C, D -> C, E -> B, P -> A
+        let graph = DependencyGraph::new(
+            "FG1.On",
+            vec![
+                manifest("A", &["B"]),
+                manifest("B", &["C"]),
+                manifest("C", &[]),
+                manifest("D", &["C"]),
+                manifest("E", &["B"]),
+                manifest("P", &["A"]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            layer_names(&graph),
+            vec![vec!["C"], vec!["B", "D"], vec!["A", "E"], vec!["P"]]
+        );
+        let names: Vec<&str> = graph
+            .manifests()
+            .iter()
+            .map(|manifest| manifest.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["C", "B", "D", "A", "E", "P"]);
+    }
+
+    #[test]
+    fn circular_dependency() {
+        let graph = DependencyGraph::new(
+            "FG1.On",
+            vec![
+                manifest("P", &["A"]),
+                manifest("A", &["B"]),
+                manifest("B", &["C"]),
+                manifest("C", &["A"]),
+            ],
+        );
+        assert_eq!(
+            graph.err().unwrap().to_string(),
+            "Circular dependency in FG1.On : A -> B -> C -> A"
+        );
+
+        let graph = DependencyGraph::new("FG1.On", vec![manifest("A", &["A"])]);
+        assert_eq!(
+            graph.err().unwrap().to_string(),
+            "Circular dependency in FG1.On : A -> A"
+        );
+    }
+
+    #[test]
+    fn missing_dependency_app() {
+        let graph = DependencyGraph::new("FG1.On", vec![manifest("A", &["B"])]);
+        assert_eq!(
+            graph.err().unwrap().to_string(),
+            "Dependency application B of A is not in FG1.On"
+        );
+    }
 }
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use ara_exec::manifest::machine_manifest::{RESTART, SHUTDOWN};
+use std::process::Command;
+use std::sync::Mutex;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MachineActionError {
+    #[error("Failed to {0:?} the machine : {1}")]
+    Failed(MachineAction, String),
+}
+
+/// What happens to the machine once every function group is stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineAction {
+    Shutdown,
+    Restart,
+}
+
+impl MachineAction {
+    /// the action of a MachineFG state, None for the others e.g. Startup
+    pub fn of_state(state: &str) -> Option<Self> {
+        match state {
+            SHUTDOWN => Some(MachineAction::Shutdown),
+            RESTART => Some(MachineAction::Restart),
+            _ => None,
+        }
+    }
+}
+
+/// How EM powers off or reboots the machine at the end of MachineFG Shutdown/Restart
+pub trait MachineActionBackend: Send + Sync {
+    fn execute(&self, action: MachineAction) -> Result<()>;
+}
+
+/// reboot(2), EM needs CAP_SYS_BOOT
+pub struct RebootBackend;
+
+impl MachineActionBackend for RebootBackend {
+    fn execute(&self, action: MachineAction) -> Result<()> {
+        let command = match action {
+            MachineAction::Shutdown => libc::RB_POWER_OFF,
+            MachineAction::Restart => libc::RB_AUTOBOOT,
+        };
+
+        // reboot(2) doesn't flush the file systems
+        unsafe {
+            libc::sync();
+        }
+        if unsafe { libc::reboot(command) } < 0 {
+            let error = std::io::Error::last_os_error();
+            return Err(MachineActionError::Failed(action, error.to_string()).into());
+        }
+        Ok(())
+    }
+}
+
+/// `systemctl poweroff` or `systemctl reboot`
+pub struct SystemdBackend;
+
+impl MachineActionBackend for SystemdBackend {
+    fn execute(&self, action: MachineAction) -> Result<()> {
+        let verb = match action {
+            MachineAction::Shutdown => "poweroff",
+            MachineAction::Restart => "reboot",
+        };
+
+        let status = Command::new("systemctl")
+            .arg(verb)
+            .status()
+            .map_err(|error| MachineActionError::Failed(action, error.to_string()))?;
+        if !status.success() {
+            return Err(MachineActionError::Failed(action, status.to_string()).into());
+        }
+        Ok(())
+    }
+}
+
+/// Records the actions instead of executing them, e.g. for CI containers
+#[derive(Default)]
+pub struct RecordingBackend {
+    actions: Mutex<Vec<MachineAction>>,
+}
+
+impl RecordingBackend {
+    pub fn actions(&self) -> Vec<MachineAction> {
+        self.actions.lock().unwrap().clone()
+    }
+}
+
+impl MachineActionBackend for RecordingBackend {
+    fn execute(&self, action: MachineAction) -> Result<()> {
+        println!("machine action {:?} is recorded", action);
+        self.actions.lock().unwrap().push(action);
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use ara_exec::manifest::execution_manifest::{Log, LogOutput};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{ChildStderr, ChildStdout};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+/*
+  .---------.  stdout  .----------.  "[name] line"  .----------.
+  | process | -------> | capture  | --------------> | EM's log |
+  `---------`  stderr  `----------`       or        `----------`
+                                 `------------> <rw_oara_root>/<name>/log/<name>.log{,.1,.2..}
+*/
+
+/// Read end of the stdout/stderr pipes of a child
+pub struct Pipes {
+    pub stdout: ChildStdout,
+    pub stderr: ChildStderr,
+}
+
+/// log file rotated by size, <name>.log is the newest one
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: u32,
+}
+
+impl RotatingFile {
+    fn open(dir: &Path, name: &str, log: &Log) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.log", name));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size: log.max_size,
+            max_files: log.max_files,
+        })
+    }
+
+    fn rotated(&self, index: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    /// <name>.log -> <name>.log.1 -> .. -> <name>.log.<max_files>, the oldest one is removed
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if self.max_files == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated(index);
+                if from.exists() {
+                    std::fs::rename(&from, self.rotated(index + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> std::io::Result<()> {
+        let length = line.len() as u64 + 1;
+        if self.size > 0 && self.size + length > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.file.write_all(b"\n")?;
+        self.size += length;
+        Ok(())
+    }
+}
+
+/// Forward or write every line of the pipes until the child closes them
+/// The lines are forwarded to EM's log if the log file can't be opened.
+pub fn capture(name: &str, log: &Log, log_dir: Option<PathBuf>, pipes: Pipes) {
+    let file = match (log.output, log_dir) {
+        (LogOutput::Forward, _) => None,
+        (LogOutput::File, None) => {
+            println!("{} : no r/w root for the log file, forward it", name);
+            None
+        }
+        (LogOutput::File, Some(log_dir)) => match RotatingFile::open(&log_dir, name, log) {
+            Ok(file) => Some(Arc::new(Mutex::new(file))),
+            Err(error) => {
+                println!("{} : failed to open the log file, forward it : {:?}", name, error);
+                None
+            }
+        },
+    };
+
+    match (
+        tokio::process::ChildStdout::from_std(pipes.stdout),
+        tokio::process::ChildStderr::from_std(pipes.stderr),
+    ) {
+        (Ok(stdout), Ok(stderr)) => {
+            tokio::spawn(copy_lines(name.to_owned(), stdout, file.clone(), false));
+            tokio::spawn(copy_lines(name.to_owned(), stderr, file, true));
+        }
+        (stdout, stderr) => {
+            println!(
+                "{} : failed to capture the output : {:?} {:?}",
+                name,
+                stdout.err(),
+                stderr.err()
+            );
+        }
+    }
+}
+
+async fn copy_lines<R>(
+    name: String,
+    pipe: R,
+    file: Option<Arc<Mutex<RotatingFile>>>,
+    stderr: bool,
+) where
+    R: AsyncRead + Unpin,
+{
+    let mut lines = BufReader::new(pipe).split(b'\n');
+    while let Ok(Some(line)) = lines.next_segment().await {
+        match &file {
+            Some(file) => {
+                if let Err(error) = file.lock().unwrap().write_line(&line) {
+                    println!("{} : failed to write the log file : {:?}", name, error);
+                }
+            }
+            None if stderr => eprintln!("[{}] {}", name, String::from_utf8_lossy(&line)),
+            None => println!("[{}] {}", name, String::from_utf8_lossy(&line)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate() {
+        let dir = std::env::temp_dir().join("output-t1");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        let log = Log {
+            output: LogOutput::File,
+            max_size: 10,
+            max_files: 2,
+        };
+        let mut file = RotatingFile::open(&dir, "APP", &log).unwrap();
+        for line in ["line1", "line2", "line3", "line4"] {
+            file.write_line(line.as_bytes()).unwrap();
+        }
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("APP.log"), "line4\n");
+        assert_eq!(read("APP.log.1"), "line3\n");
+        assert_eq!(read("APP.log.2"), "line2\n");
+        // the oldest one is gone
+        assert!(!dir.join("APP.log.3").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
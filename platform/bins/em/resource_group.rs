@@ -0,0 +1,187 @@
+use anyhow::Result;
+use ara_exec::manifest::machine_manifest::ResourceGroup;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// cgroup v2 root of EM if not given
+pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup/oara";
+
+// period of cpu.max in microseconds
+const CPU_PERIOD: u64 = 100_000;
+
+#[derive(Debug, Error)]
+pub enum ResourceGroupError {
+    #[error("Failed to set up resource group {0} : {1}")]
+    SetupFailed(String, String),
+}
+
+/*
+    <cgroup root>                  cgroup.subtree_control: +cpu +memory +pids
+      |- <resource group>          cpu.weight, cpu.max, memory.max, pids.max
+      |     `- cgroup.procs        processes of the resource group
+      `- ...
+*/
+
+/// cgroup v2 hierarchy of the resource groups in the machine manifest
+pub struct ResourceGroups {
+    root: PathBuf,
+    // resource group / oom_kill count of memory.events already reported
+    oom_kills: Mutex<HashMap<String, u64>>,
+}
+
+impl ResourceGroups {
+    /// Create a cgroup for every resource group under `root`, and apply its limits
+    pub fn create<P: Into<PathBuf>>(
+        root: P,
+        resource_groups: &HashMap<String, ResourceGroup>,
+    ) -> Result<Self> {
+        let root = root.into();
+        let setup_failed = |name: &str, error: std::io::Error| {
+            ResourceGroupError::SetupFailed(name.to_owned(), error.to_string())
+        };
+
+        std::fs::create_dir_all(&root).map_err(|error| setup_failed("root", error))?;
+        // a controller is enabled one by one, e.g. `+memory` fails if it's not available
+        for controller in ["+cpu", "+memory", "+pids"] {
+            std::fs::write(root.join("cgroup.subtree_control"), controller)
+                .map_err(|error| setup_failed("root", error))?;
+        }
+
+        let mut oom_kills = HashMap::new();
+        for (name, resource_group) in resource_groups {
+            let path = root.join(name);
+            std::fs::create_dir_all(&path).map_err(|error| setup_failed(name, error))?;
+            for (file, value) in limits(resource_group) {
+                std::fs::write(path.join(file), value).map_err(|error| setup_failed(name, error))?;
+            }
+            oom_kills.insert(name.clone(), read_oom_kill(&path));
+        }
+
+        Ok(Self {
+            root,
+            oom_kills: Mutex::new(oom_kills),
+        })
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.root.join(name)
+    }
+
+    /// Whether the resource group has OOM kills which are not reported yet
+    /// Every OOM kill is reported once, to the first process of the group asking for it.
+    pub fn take_oom_kill(&self, name: &str) -> bool {
+        let count = read_oom_kill(&self.path(name));
+        let mut oom_kills = self.oom_kills.lock().unwrap();
+        let reported = oom_kills.entry(name.to_owned()).or_insert(0);
+        if count > *reported {
+            *reported += 1;
+            return true;
+        }
+        false
+    }
+}
+
+fn limits(resource_group: &ResourceGroup) -> Vec<(&'static str, String)> {
+    let mut limits = Vec::new();
+    if let Some(weight) = resource_group.cpu_weight {
+        limits.push(("cpu.weight", weight.to_string()));
+    }
+    if let Some(quota) = resource_group.cpu_quota {
+        let quota = u64::from(quota) * CPU_PERIOD / 100;
+        limits.push(("cpu.max", format!("{} {}", quota, CPU_PERIOD)));
+    }
+    if let Some(memory_max) = resource_group.memory_max {
+        limits.push(("memory.max", memory_max.to_string()));
+    }
+    if let Some(pids_max) = resource_group.pids_max {
+        limits.push(("pids.max", pids_max.to_string()));
+    }
+    limits
+}
+
+/// `oom_kill` of memory.events, 0 if it's not available
+fn read_oom_kill(path: &Path) -> u64 {
+    std::fs::read_to_string(path.join("memory.events"))
+        .ok()
+        .and_then(|events| {
+            events.lines().find_map(|line| {
+                line.strip_prefix("oom_kill ")
+                    .and_then(|count| count.trim().parse().ok())
+            })
+        })
+        .unwrap_or(0)
+}
+
+/// The child moves itself into the cgroup at `path` between fork and exec, so that it never runs
+/// outside of its resource group
+pub fn join(command: &mut Command, path: &Path) -> Result<()> {
+    let procs = CString::new(path.join("cgroup.procs").as_os_str().as_bytes())?;
+    unsafe {
+        command.pre_exec(move || {
+            // "0" is the writing process itself
+            let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let written = libc::write(fd, b"0".as_ptr() as *const libc::c_void, 1);
+            let error = std::io::Error::last_os_error();
+            libc::close(fd);
+            if written < 0 {
+                return Err(error);
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create() {
+        let root = std::env::temp_dir().join("resource_group-t1");
+        if root.exists() {
+            std::fs::remove_dir_all(&root).unwrap();
+        }
+
+        let mut resource_groups = HashMap::new();
+        resource_groups.insert(
+            "RG1".to_owned(),
+            ResourceGroup {
+                cpu_weight: Some(200),
+                cpu_quota: Some(150),
+                memory_max: Some(1048576),
+                pids_max: None,
+            },
+        );
+        let groups = ResourceGroups::create(&root, &resource_groups).unwrap();
+
+        let read = |file: &str| std::fs::read_to_string(root.join("RG1").join(file)).unwrap();
+        assert_eq!(read("cpu.weight"), "200");
+        assert_eq!(read("cpu.max"), "150000 100000");
+        assert_eq!(read("memory.max"), "1048576");
+        assert!(!root.join("RG1").join("pids.max").exists());
+
+        // a fake hierarchy, the kernel would update memory.events
+        std::fs::write(root.join("RG1").join("memory.events"), "oom 1\noom_kill 1\n").unwrap();
+        assert!(groups.take_oom_kill("RG1"));
+        assert!(!groups.take_oom_kill("RG1"));
+
+        // the child writes its pid to cgroup.procs
+        std::fs::write(root.join("RG1").join("cgroup.procs"), "").unwrap();
+        let mut command = Command::new("true");
+        join(&mut command, &groups.path("RG1")).unwrap();
+        assert!(command.status().unwrap().success());
+        assert_eq!(read("cgroup.procs"), "0");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -46,7 +46,7 @@ pub fn is_platform_initialized() -> bool {
 ///
 /// The general advice for application developers is to call ara::core::Initialize
 /// right at the entry point of the application.
-
+///
 /// [SWS_CORE_10001]{DRAFT} Definition of API function ara::core::Initialize
 /// Syntax: Result< void > Initialize () noexcept;
 /// Return value: Result< void > a Result with an error code, in case an error occurred
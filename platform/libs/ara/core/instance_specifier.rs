@@ -119,6 +119,6 @@ impl<'a> InstanceSpecifier<'a> {
     // to_string is not adequate to Rust
     #[allow(unused)]
     fn as_str(&self) -> &'a str {
-        &self.meta_model_identifier
+        self.meta_model_identifier
     }
 }
@@ -0,0 +1,174 @@
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Framing of the bincode messages between EM and its clients (StateClient, ExecutionClient)
+///
+///  0        1                 5
+///  +--------+-----------------+---------------------------+
+///  | version| length (u32 BE) | bincode payload (length)  |
+///  +--------+-----------------+---------------------------+
+pub const PROTOCOL_VERSION: u8 = 1;
+pub const MAX_FRAME_SIZE: usize = 64 * 1024;
+const HEADER_SIZE: usize = 5;
+
+#[derive(Debug, Error)]
+pub enum FrameError {
+    #[error("Connection closed")]
+    Closed,
+    #[error("I/O error : {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Unsupported protocol version : {0}")]
+    UnsupportedVersion(u8),
+    #[error("Frame is too large : {0} bytes")]
+    TooLarge(usize),
+    #[error("Malformed frame : {0}")]
+    Malformed(String),
+}
+
+pub async fn write_frame<W, T>(writer: &mut W, message: &T) -> Result<(), FrameError>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let payload =
+        bincode::serialize(message).map_err(|error| FrameError::Malformed(error.to_string()))?;
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(FrameError::TooLarge(payload.len()));
+    }
+
+    let mut frame = Vec::with_capacity(HEADER_SIZE + payload.len());
+    frame.push(PROTOCOL_VERSION);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+
+    // single write not to interleave with other frames
+    writer.write_all(&frame).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// `FrameError::Closed` if the peer closed the connection between frames
+pub async fn read_frame<R, T>(reader: &mut R) -> Result<T, FrameError>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let mut header = [0u8; HEADER_SIZE];
+    let len = reader.read(&mut header).await?;
+    if len == 0 {
+        return Err(FrameError::Closed);
+    }
+    reader.read_exact(&mut header[len..]).await?;
+
+    let version = header[0];
+    if version != PROTOCOL_VERSION {
+        return Err(FrameError::UnsupportedVersion(version));
+    }
+
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(FrameError::TooLarge(length));
+    }
+
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload).await?;
+
+    bincode::deserialize(&payload).map_err(|error| FrameError::Malformed(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function_group::FunctionGroupState;
+    use crate::state_client::SmClientCommand;
+
+    #[tokio::test]
+    async fn coalesced_frames() {
+        let (mut client, mut server) = tokio::io::duplex(MAX_FRAME_SIZE * 2);
+
+        // long names don't fit into a single read of the old 1024 bytes buffer
+        let long_state = FunctionGroupState::new("FG".repeat(1000), "On".repeat(1000));
+        write_frame(&mut client, &SmClientCommand::GetInitialState)
+            .await
+            .unwrap();
+        write_frame(&mut client, &SmClientCommand::SetState(long_state.clone()))
+            .await
+            .unwrap();
+        drop(client);
+
+        assert!(matches!(
+            read_frame::<_, SmClientCommand>(&mut server).await.unwrap(),
+            SmClientCommand::GetInitialState
+        ));
+        match read_frame::<_, SmClientCommand>(&mut server).await.unwrap() {
+            SmClientCommand::SetState(state) => assert_eq!(state, long_state),
+            command => panic!("Invalid command {:?}", command),
+        }
+        assert!(matches!(
+            read_frame::<_, SmClientCommand>(&mut server).await,
+            Err(FrameError::Closed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn partial_reads() {
+        let (mut client, mut server) = tokio::io::duplex(MAX_FRAME_SIZE);
+        let mut frame = Vec::new();
+        write_frame(&mut frame, &SmClientCommand::GetInitialState)
+            .await
+            .unwrap();
+
+        let handle = tokio::spawn(async move {
+            // byte by byte
+            for byte in frame {
+                client.write_all(&[byte]).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+
+        assert!(matches!(
+            read_frame::<_, SmClientCommand>(&mut server).await.unwrap(),
+            SmClientCommand::GetInitialState
+        ));
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_frames() {
+        // version
+        let mut frame: &[u8] = &[PROTOCOL_VERSION + 1, 0, 0, 0, 0];
+        assert!(matches!(
+            read_frame::<_, SmClientCommand>(&mut frame).await,
+            Err(FrameError::UnsupportedVersion(2))
+        ));
+
+        // oversized
+        let length = (MAX_FRAME_SIZE as u32 + 1).to_be_bytes();
+        let mut frame: &[u8] = &[
+            PROTOCOL_VERSION,
+            length[0],
+            length[1],
+            length[2],
+            length[3],
+        ];
+        assert!(matches!(
+            read_frame::<_, SmClientCommand>(&mut frame).await,
+            Err(FrameError::TooLarge(_))
+        ));
+
+        // unknown command
+        let mut frame: &[u8] = &[PROTOCOL_VERSION, 0, 0, 0, 4, 0xff, 0xff, 0xff, 0xff];
+        assert!(matches!(
+            read_frame::<_, SmClientCommand>(&mut frame).await,
+            Err(FrameError::Malformed(_))
+        ));
+
+        // truncated
+        let mut frame: &[u8] = &[PROTOCOL_VERSION, 0, 0, 0, 4, 0];
+        assert!(matches!(
+            read_frame::<_, SmClientCommand>(&mut frame).await,
+            Err(FrameError::Io(_))
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod codec;
 pub mod execution_client;
 pub mod function_group;
 pub mod manifest;
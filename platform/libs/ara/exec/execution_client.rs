@@ -1,15 +1,20 @@
 //use std::sync::Arc;
 
-//use anyhow::Result;
+use crate::codec::{read_frame, write_frame};
+use anyhow::Result;
 //use std::error::Error;
+use serde::{Deserialize, Serialize};
 use std::future::Future;
-//use thiserror::Error;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tokio::net::UnixStream;
 use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::mpsc;
 //use tokio::task::JoinHandle;
-//use tokio::time::{sleep, Duration};
+use tokio::time::{timeout, Duration};
+
+pub const OARA_EM_DOMAIN_SOCKET: &str = "/tmp/oara_em_domain_socket";
 
-/*
 /// [SWS_EM_02000] Definition of API enum `ara::exec::ExecutionState`
 ///
 /// Defines the internal states of a Process (see 7.3.1).
@@ -17,9 +22,30 @@ use tokio::sync::mpsc;
 ///
 /// # Values:
 /// - `RUNNING = 0`: After a Process has been started by Execution Management,
-///    it reports the `ExecutionState::RUNNING`.
+///   it reports the `ExecutionState::RUNNING`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ExecutionState {
-    RUNNING = 0
+    Running = 0,
+}
+
+#[derive(Debug, Clone, Error, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ExecutionClientError {
+    #[error("Communication error occurred.")]
+    CommunicationError,
+    #[error("Given terminationHandler doesn’t contain a callable function.")]
+    InvalidArgument,
+    #[error("Invalid transition request")]
+    InvalidTransition,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExecutionClientCommand {
+    ReportExecutionState(ExecutionState),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExecutionClientResponse {
+    ReportExecutionState(Result<(), ExecutionClientError>),
 }
 
 /// [SWS_EM_02541]{DRAFT} Definition of API type ara::exec::ExecutionError d
@@ -29,7 +55,17 @@ pub enum ExecutionState {
 /// Symbol: ExecutionError
 /// Syntax: using ExecutionError = std::uint32_t;
 /// Description: Represents the execution error.
-type ExecutionError = u32;
+pub type ExecutionError = u32;
+
+/// [SWS_EM_02543]{DRAFT} Default value for ExecutionError
+/// In case of Unexpected Termination or Unexpected Self-termination of a Modelled Process which
+/// does not have an executionError configured, Execution Management shall report the
+/// ExecutionError value 1.
+pub const DEFAULT_EXECUTION_ERROR: ExecutionError = 1;
+
+/// Reported instead of the configured executionError when the Process is killed by the OOM killer
+/// of its resource group. Not to be configured as executionError.
+pub const OOM_EXECUTION_ERROR: ExecutionError = ExecutionError::MAX;
 
 /// [SWS_EM_02544] Definition of API class ara::exec::ExecutionErrorEvent
 /// Kind: struct
@@ -39,32 +75,24 @@ type ExecutionError = u32;
 /// Symbol: ExecutionErrorEvent
 /// Syntax: struct ExecutionErrorEvent final {...};
 /// Description: Represents an execution error event which happens in a Function Group.
-struct ExecutionErrorEvent {
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionErrorEvent {
     /// [SWS_EM_02545]{DRAFT} Definition of API variable ara::exec::ExecutionErrorEvent::executionError
     /// Symbol: executionError
     /// Type: ExecutionError
     /// Syntax: ExecutionError executionError;
     /// Description: The execution error of the Process which unexpectedly terminated .
-    execution_error : ExecutionError,
+    pub execution_error: ExecutionError,
 
     /// [SWS_EM_02546]{DRAFT} Definition of API variable ara::exec::ExecutionErrorEvent::functionGroup
     /// Symbol: functionGroup
     /// Type: ara::core::StringView
     /// Syntax: ara::core::StringView functionGroup;
     /// Description: The function group in which the error occurred .
-    function_group: String,
-}
-
-#[derive(Error, Debug)]
-enum ExecutionClientError {
-    #[error("Communication error occurred.")]
-    kCommunicationError,
-    #[error("Given terminationHandler doesn’t contain a callable function.")]
-    kInvalidArgument,
-    #[error("Invalid transition request")]
-    kInvalidTransition,
+    pub function_group: String,
 }
 
+/*
 /// [SWS_EM_02001] Definition of API class ara::exec::ExecutionClient
 /// Symbol: ExecutionClient
 /// Syntax: class ExecutionClient final {...};
@@ -131,7 +159,36 @@ impl ExecutionClient {
     pub fn create(termination_handler: Box<dyn Fn()>) -> Result<Self> {
         ExecutionClient::new(termination_handler)
     }
+}*/
+
+pub struct ExecutionClient {
+    #[allow(unused)]    // FIXME
+    signal_channel: Option<mpsc::Receiver<()>>,
+    socket_path: PathBuf,
+}
+
+impl Default for ExecutionClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExecutionClient {
+    pub fn new() -> Self {
+        ExecutionClient {
+            //signal_handler: None,
+            signal_channel: None,
+            socket_path: PathBuf::from(OARA_EM_DOMAIN_SOCKET),
+        }
+    }
 
+    /// domain socket of Execution Management, `OARA_EM_DOMAIN_SOCKET` if not given
+    pub fn with_socket_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.socket_path = path.as_ref().to_path_buf();
+        self
+    }
+
+    /// [SWS_EM_02003] Definition of API function ara::exec::ExecutionClient::ReportExecutionState
     /// Symbol: ReportExecutionState(ExecutionState state)
     /// Syntax: ara::core::Result< void > ReportExecutionState (ExecutionState state) const noexcept;
     /// Parameters (in): state Value of the Execution State
@@ -142,24 +199,25 @@ impl ExecutionClient {
     ///                                                   Management, e.g. unable to report state for Non-reporting Process.
     ///        ara::exec::ExecErrc::kInvalidTransition : Invalid transition request (e.g. to Running when already in Running state)
     /// Description: Interface for a Process to report its internal state to Execution Management.
-    pub fn report_execution_state(state: ExecutionState) -> Result<()> {
-
-        Ok(())
-    }
-
-}*/
+    ///
+    /// Execution Management identifies the reporting process by the credentials of the domain socket
+    pub async fn report_execution_state(&self, state: ExecutionState) -> Result<()> {
+        let mut socket = UnixStream::connect(&self.socket_path)
+            .await
+            .map_err(|_| ExecutionClientError::CommunicationError)?;
 
-pub struct ExecutionClient {
-    #[allow(unused)]    // FIXME
-    signal_channel: Option<mpsc::Receiver<()>>,
-}
+        let command = ExecutionClientCommand::ReportExecutionState(state);
+        write_frame(&mut socket, &command)
+            .await
+            .map_err(|_| ExecutionClientError::CommunicationError)?;
 
-impl ExecutionClient {
-    #[allow(unused)]    // FIXME
-    fn new() -> Self {
-        ExecutionClient {
-            //signal_handler: None,
-            signal_channel: None,
+        // wait the result from server
+        match timeout(Duration::from_secs(1), read_frame(&mut socket)).await {
+            Ok(Ok(ExecutionClientResponse::ReportExecutionState(response))) => {
+                response.map_err(|error| error.into())
+            }
+            // error on read, malformed or timeout
+            _ => Err(ExecutionClientError::CommunicationError.into()),
         }
     }
 
@@ -226,4 +284,86 @@ mod tests {
         // not sure how to test SIGTERM
         let _execution_client = ExecutionClient::new().run_with_channel();
     }
+
+    async fn execution_management(socket_path: PathBuf, response: ExecutionClientResponse) {
+        if tokio::fs::metadata(&socket_path).await.is_ok() {
+            tokio::fs::remove_file(&socket_path).await.unwrap();
+        }
+
+        let listener = tokio::net::UnixListener::bind(&socket_path).unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+
+        let request_command = read_frame::<_, ExecutionClientCommand>(&mut stream)
+            .await
+            .unwrap();
+        match request_command {
+            ExecutionClientCommand::ReportExecutionState(state) => {
+                assert_eq!(state, ExecutionState::Running);
+            }
+        }
+
+        write_frame(&mut stream, &response).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn report_execution_state() {
+        let socket_path = std::env::temp_dir().join("test_em_domain_socket1");
+        let handle = tokio::spawn(execution_management(
+            socket_path.clone(),
+            ExecutionClientResponse::ReportExecutionState(Ok(())),
+        ));
+
+        // wait a second to create domain socket
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let execution_client = ExecutionClient::new().with_socket_path(&socket_path);
+        let result = execution_client
+            .report_execution_state(ExecutionState::Running)
+            .await;
+        assert!(result.is_ok());
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn report_execution_state_invalid_transition() {
+        let socket_path = std::env::temp_dir().join("test_em_domain_socket2");
+        let handle = tokio::spawn(execution_management(
+            socket_path.clone(),
+            ExecutionClientResponse::ReportExecutionState(Err(
+                ExecutionClientError::InvalidTransition,
+            )),
+        ));
+
+        // wait a second to create domain socket
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let execution_client = ExecutionClient::new().with_socket_path(&socket_path);
+        let error = execution_client
+            .report_execution_state(ExecutionState::Running)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.downcast_ref::<ExecutionClientError>().unwrap(),
+            &ExecutionClientError::InvalidTransition
+        );
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn report_execution_state_without_em() {
+        let socket_path = std::env::temp_dir().join("test_em_domain_socket3");
+        let execution_client = ExecutionClient::new().with_socket_path(&socket_path);
+        let error = execution_client
+            .report_execution_state(ExecutionState::Running)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.downcast_ref::<ExecutionClientError>().unwrap(),
+            &ExecutionClientError::CommunicationError
+        );
+    }
 }
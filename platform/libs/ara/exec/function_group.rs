@@ -2,7 +2,8 @@ use serde::{Deserialize, Serialize};
 
 pub type FunctionGroup = String;
 
-pub const MACHINE_FG: &str = "MachineFg";
+/// the only source of function group and state names known to EM and SM
+pub const MACHINE_FG: &str = "MachineFG";
 pub const STARTUP: &str = "Startup";
 pub const RESTART: &str = "Restart";
 pub const SHUTDOWN: &str = "Shutdown";
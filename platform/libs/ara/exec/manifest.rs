@@ -1,3 +1,5 @@
+pub mod credential;
+pub mod dependency;
 pub mod execution_manifest;
 pub mod machine_manifest;
 pub mod parse;
@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::fmt;
+
+/// Linux capabilities indexed by their number, see capabilities(7)
+pub const CAPABILITIES: [&str; 41] = [
+    "CAP_CHOWN",
+    "CAP_DAC_OVERRIDE",
+    "CAP_DAC_READ_SEARCH",
+    "CAP_FOWNER",
+    "CAP_FSETID",
+    "CAP_KILL",
+    "CAP_SETGID",
+    "CAP_SETUID",
+    "CAP_SETPCAP",
+    "CAP_LINUX_IMMUTABLE",
+    "CAP_NET_BIND_SERVICE",
+    "CAP_NET_BROADCAST",
+    "CAP_NET_ADMIN",
+    "CAP_NET_RAW",
+    "CAP_IPC_LOCK",
+    "CAP_IPC_OWNER",
+    "CAP_SYS_MODULE",
+    "CAP_SYS_RAWIO",
+    "CAP_SYS_CHROOT",
+    "CAP_SYS_PTRACE",
+    "CAP_SYS_PACCT",
+    "CAP_SYS_ADMIN",
+    "CAP_SYS_BOOT",
+    "CAP_SYS_NICE",
+    "CAP_SYS_RESOURCE",
+    "CAP_SYS_TIME",
+    "CAP_SYS_TTY_CONFIG",
+    "CAP_MKNOD",
+    "CAP_LEASE",
+    "CAP_AUDIT_WRITE",
+    "CAP_AUDIT_CONTROL",
+    "CAP_SETFCAP",
+    "CAP_MAC_OVERRIDE",
+    "CAP_MAC_ADMIN",
+    "CAP_SYSLOG",
+    "CAP_WAKE_ALARM",
+    "CAP_BLOCK_SUSPEND",
+    "CAP_AUDIT_READ",
+    "CAP_PERFMON",
+    "CAP_BPF",
+    "CAP_CHECKPOINT_RESTORE",
+];
+
+/// number of a capability, e.g. CAP_NET_BIND_SERVICE or net_bind_service
+pub fn capability_number(name: &str) -> Option<u32> {
+    let name = name.to_ascii_uppercase();
+    let name = if name.starts_with("CAP_") {
+        name
+    } else {
+        format!("CAP_{}", name)
+    };
+    CAPABILITIES
+        .iter()
+        .position(|capability| *capability == name)
+        .map(|number| number as u32)
+}
+
+/// user or group, by id or by name
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Identity {
+    Id(u32),
+    Name(String),
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Identity::Id(id) => write!(f, "{}", id),
+            Identity::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl Identity {
+    /// uid and primary gid of the user, the gid is None for an id without passwd entry
+    pub fn resolve_user(&self) -> Option<(u32, Option<u32>)> {
+        match self {
+            Identity::Id(uid) => Some((*uid, getpw(|passwd, buffer, result| unsafe {
+                libc::getpwuid_r(*uid, passwd, buffer.as_mut_ptr(), buffer.len(), result)
+            })
+            .map(|(_, gid)| gid))),
+            Identity::Name(name) => {
+                let name = CString::new(name.as_str()).ok()?;
+                getpw(|passwd, buffer, result| unsafe {
+                    libc::getpwnam_r(
+                        name.as_ptr(),
+                        passwd,
+                        buffer.as_mut_ptr(),
+                        buffer.len(),
+                        result,
+                    )
+                })
+                .map(|(uid, gid)| (uid, Some(gid)))
+            }
+        }
+    }
+
+    /// gid of the group, an id is taken as it is
+    pub fn resolve_group(&self) -> Option<u32> {
+        match self {
+            Identity::Id(gid) => Some(*gid),
+            Identity::Name(name) => {
+                let name = CString::new(name.as_str()).ok()?;
+                let mut group: libc::group = unsafe { std::mem::zeroed() };
+                let mut result = std::ptr::null_mut();
+                with_buffer(|buffer| unsafe {
+                    libc::getgrnam_r(
+                        name.as_ptr(),
+                        &mut group,
+                        buffer.as_mut_ptr(),
+                        buffer.len(),
+                        &mut result,
+                    )
+                })?;
+                (!result.is_null()).then_some(group.gr_gid)
+            }
+        }
+    }
+}
+
+fn getpw<F>(mut lookup: F) -> Option<(u32, u32)>
+where
+    F: FnMut(&mut libc::passwd, &mut Vec<libc::c_char>, &mut *mut libc::passwd) -> libc::c_int,
+{
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    with_buffer(|buffer| lookup(&mut passwd, buffer, &mut result))?;
+    (!result.is_null()).then_some((passwd.pw_uid, passwd.pw_gid))
+}
+
+/// retry the reentrant lookup with a larger buffer on ERANGE
+fn with_buffer<F>(mut lookup: F) -> Option<()>
+where
+    F: FnMut(&mut Vec<libc::c_char>) -> libc::c_int,
+{
+    let mut buffer = vec![0; 1024];
+    loop {
+        match lookup(&mut buffer) {
+            0 => return Some(()),
+            libc::ERANGE if buffer.len() < 1 << 20 => buffer.resize(buffer.len() * 2, 0),
+            _ => return None,
+        }
+    }
+}
+
+/// Linux capabilities of the process
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Capability {
+    /// the bounding set is limited to these if given
+    #[serde(default)]
+    pub bounding: Option<Vec<String>>,
+    /// raised and kept across execve, e.g. for a process running as a non-root user
+    #[serde(default)]
+    pub ambient: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        assert_eq!(capability_number("CAP_CHOWN"), Some(0));
+        assert_eq!(capability_number("net_bind_service"), Some(10));
+        assert_eq!(capability_number("CAP_UNKNOWN"), None);
+
+        assert_eq!(Identity::Name("root".to_owned()).resolve_user(), Some((0, Some(0))));
+        assert_eq!(Identity::Id(0).resolve_user(), Some((0, Some(0))));
+        // no passwd entry
+        assert_eq!(Identity::Id(54321).resolve_user(), Some((54321, None)));
+        assert_eq!(Identity::Name("no-such-user".to_owned()).resolve_user(), None);
+
+        assert_eq!(Identity::Name("root".to_owned()).resolve_group(), Some(0));
+        assert_eq!(Identity::Name("no-such-group".to_owned()).resolve_group(), None);
+    }
+}
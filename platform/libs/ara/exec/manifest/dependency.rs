@@ -0,0 +1,161 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::function_group::FunctionGroupState;
+
+#[derive(Debug, Error)]
+pub enum DependencyError {
+    #[error("Invalid application dependency format: {0}")]
+    InvalidApplicationDependencyFormat(String),
+    #[error("Invalid mode dependency format: {0}")]
+    InvalidModeDependencyFormat(String),
+}
+
+/// "<name>.<state>", both non-empty
+fn split(dependency: &str) -> Option<(&str, &str)> {
+    dependency
+        .split_once('.')
+        .filter(|(name, state)| !name.is_empty() && !state.is_empty())
+}
+
+/// process state of another application to start after, e.g. "APP.Running" in a manifest
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct AppDependency {
+    pub app: String,
+    /// one of `process_mode` of the machine manifest
+    pub state: String,
+}
+
+impl AppDependency {
+    pub fn new<A: Into<String>, S: Into<String>>(app: A, state: S) -> Self {
+        Self {
+            app: app.into(),
+            state: state.into(),
+        }
+    }
+}
+
+impl FromStr for AppDependency {
+    type Err = DependencyError;
+
+    fn from_str(dependency: &str) -> Result<Self, Self::Err> {
+        let (app, state) = split(dependency).ok_or_else(|| {
+            DependencyError::InvalidApplicationDependencyFormat(dependency.to_owned())
+        })?;
+        Ok(Self::new(app, state))
+    }
+}
+
+impl TryFrom<String> for AppDependency {
+    type Error = DependencyError;
+
+    fn try_from(dependency: String) -> Result<Self, Self::Error> {
+        dependency.parse()
+    }
+}
+
+impl From<AppDependency> for String {
+    fn from(dependency: AppDependency) -> Self {
+        dependency.to_string()
+    }
+}
+
+impl fmt::Display for AppDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.app, self.state)
+    }
+}
+
+/// function group state the process runs in, e.g. "MachineFG.Startup" in a manifest
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ModeDependency {
+    pub function_group: String,
+    pub state: String,
+}
+
+impl ModeDependency {
+    pub fn new<F: Into<String>, S: Into<String>>(function_group: F, state: S) -> Self {
+        Self {
+            function_group: function_group.into(),
+            state: state.into(),
+        }
+    }
+
+    pub fn matches(&self, fg_state: &FunctionGroupState) -> bool {
+        self.function_group == fg_state.function_group
+            && self.state == fg_state.function_group_state
+    }
+}
+
+impl FromStr for ModeDependency {
+    type Err = DependencyError;
+
+    fn from_str(dependency: &str) -> Result<Self, Self::Err> {
+        let (function_group, state) = split(dependency)
+            .ok_or_else(|| DependencyError::InvalidModeDependencyFormat(dependency.to_owned()))?;
+        Ok(Self::new(function_group, state))
+    }
+}
+
+impl TryFrom<String> for ModeDependency {
+    type Error = DependencyError;
+
+    fn try_from(dependency: String) -> Result<Self, Self::Error> {
+        dependency.parse()
+    }
+}
+
+impl From<ModeDependency> for String {
+    fn from(dependency: ModeDependency) -> Self {
+        dependency.to_string()
+    }
+}
+
+impl fmt::Display for ModeDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.function_group, self.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let dependency: AppDependency = "APP.Running".parse().unwrap();
+        assert_eq!(dependency, AppDependency::new("APP", "Running"));
+        assert_eq!(dependency.to_string(), "APP.Running");
+
+        let dependency: ModeDependency = "MachineFG.Startup".parse().unwrap();
+        assert_eq!(dependency, ModeDependency::new("MachineFG", "Startup"));
+        assert!(dependency.matches(&FunctionGroupState::new(
+            "MachineFG".to_owned(),
+            "Startup".to_owned()
+        )));
+
+        for dependency in ["APPRunning", "APP.", ".Running"] {
+            assert_eq!(
+                dependency
+                    .parse::<AppDependency>()
+                    .err()
+                    .unwrap()
+                    .to_string(),
+                format!("Invalid application dependency format: {}", dependency)
+            );
+        }
+        assert_eq!(
+            "MachineFGStartup"
+                .parse::<ModeDependency>()
+                .err()
+                .unwrap()
+                .to_string(),
+            "Invalid mode dependency format: MachineFGStartup"
+        );
+    }
+}
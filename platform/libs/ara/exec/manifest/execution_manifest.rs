@@ -2,37 +2,141 @@ use std::{collections::HashMap, io::Read};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+use super::credential::{capability_number, Capability, Identity};
+use super::dependency::{AppDependency, ModeDependency};
 use super::machine_manifest::MachineManifest;
+use crate::execution_client::ExecutionError;
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct EnterExitTimeout {
-    enter: i32,
-    exit: i32,
+    pub enter: i32,
+    pub exit: i32,
+}
+
+/// resource of setrlimit(2), e.g. NOFILE for RLIMIT_NOFILE
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum RlimitResource {
+    As,
+    Core,
+    Cpu,
+    Data,
+    Fsize,
+    Memlock,
+    Nofile,
+    Nproc,
+    Stack,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rlimit {
+    pub soft: u64,
+    pub hard: u64,
+}
+
+/// scheduling policy of sched(7)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SchedulingPolicy {
+    #[default]
+    Other,
+    Fifo,
+    Rr,
+}
+
+impl SchedulingPolicy {
+    /// range of the static priority
+    pub fn priority_range(&self) -> std::ops::RangeInclusive<i32> {
+        match self {
+            SchedulingPolicy::Other => 0..=0,
+            SchedulingPolicy::Fifo | SchedulingPolicy::Rr => 1..=99,
+        }
+    }
+}
+
+/// Where stdout and stderr of the process go
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutput {
+    /// every line is logged by EM with the process name as prefix
+    #[default]
+    Forward,
+    /// <rw_oara_root>/<name>/log/<name>.log, rotated by size
+    File,
+}
+
+fn default_log_max_size() -> u64 {
+    1024 * 1024
+}
+
+fn default_log_max_files() -> u32 {
+    3
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Log {
+    #[serde(default)]
+    pub output: LogOutput,
+    /// bytes of a log file before it is rotated
+    #[serde(default = "default_log_max_size")]
+    pub max_size: u64,
+    /// rotated log files to keep, e.g. <name>.log.1 ~ <name>.log.3
+    #[serde(default = "default_log_max_files")]
+    pub max_files: u32,
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self {
+            output: LogOutput::default(),
+            max_size: default_log_max_size(),
+            max_files: default_log_max_files(),
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 enum ExecutionManifestError {
     //#[error("Empty process name")]
     //EmptyProcessName(),
-    #[error("Inavlid application dependency format: {0} for {1}")]
-    InvalidApplicationDependencyFormat(String, String),
     #[error("Inavlid application dependency: {0} for {1}")]
     InvalidApplicationDependencyMode(String, String),
-    #[error("Inavlid mode dependency format: {0} for {1}")]
-    InvalidModeDependencyFormat(String, String),
     #[error("Function group({0}) doesn't exist for {1}")]
     FGNotExist(String, String),
     #[error("No mode({0}) for {1}")]
     NoModeInFG(String, String),
+    #[error("Unknown user: {0} for {1}")]
+    UnknownUser(String, String),
+    #[error("Unknown group: {0} for {1}")]
+    UnknownGroup(String, String),
+    #[error("Unknown capability: {0} for {1}")]
+    UnknownCapability(String, String),
+    #[error("Ambient capability({0}) is not in the bounding set for {1}")]
+    AmbientCapabilityNotBounded(String, String),
+    #[error("Resource group({0}) doesn't exist for {1}")]
+    ResourceGroupNotExist(String, String),
+    #[error("Soft limit is greater than hard limit of {0:?} for {1}")]
+    InvalidRlimit(RlimitResource, String),
+    #[error("Invalid scheduling priority({0}) under {1:?} for {2}")]
+    InvalidSchedulingPriority(i32, SchedulingPolicy, String),
+    #[error("Empty cpu affinity for {0}")]
+    EmptyCpuAffinity(String),
+    #[error("Zero log max_size for {0}")]
+    ZeroLogMaxSize(String),
 }
 
 // DO NOT ADD Default derive
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ExecutionManifest {
     pub name: String,
+    /// binary to launch, `name` if not given
+    /// A relative path is resolved against RO_OARA_ROOT, or <RW_OARA_ROOT>/<App>/bin for the
+    /// manifest of an App under RW_OARA_ROOT.
+    #[serde(default)]
+    pub executable: Option<String>,
     #[serde(default)]
     pub environmental_variable: HashMap<String, String>,
     #[serde(default)]
@@ -41,43 +145,99 @@ pub struct ExecutionManifest {
     pub enter_exit_timeout: Option<EnterExitTimeout>,
     #[serde(default)]
     pub reporting_behavior: bool,
+    /// restarts on unexpected termination before the Undefined Function Group State
     #[serde(default)]
     pub number_of_restart: i32,
+    /// milliseconds before the first restart, doubled for every next attempt
+    #[serde(default)]
+    pub restart_backoff: u32,
+    /// "APP.Running" starts after APP reports Running, "APP.Terminated" after APP exits with 0
+    #[serde(default)]
+    pub app_dependency: Vec<AppDependency>,
+    /// function group states the process runs in, e.g. "MachineFG.Startup"
+    #[serde(default)]
+    pub mode_dependency: Vec<ModeDependency>,
+    /// reported to SM if the process terminates unexpectedly, `DEFAULT_EXECUTION_ERROR` if not given
+    #[serde(default)]
+    pub execution_error: Option<ExecutionError>,
+    /// uid or user name, EM's user if not given
     #[serde(default)]
-    pub app_dependency: Vec<String>,
+    pub user: Option<Identity>,
+    /// gid or group name, the primary group of `user` if not given
     #[serde(default)]
-    pub mode_dependency: Vec<String>,
+    pub group: Option<Identity>,
+    /// replace the supplementary groups of EM, cleared if `user` is given without them
+    #[serde(default)]
+    pub supplementary_groups: Vec<Identity>,
+    #[serde(default)]
+    pub capability: Option<Capability>,
+    /// resource group of the machine manifest the process is placed in
+    #[serde(default)]
+    pub resource_group: Option<String>,
+    #[serde(default)]
+    pub rlimit: HashMap<RlimitResource, Rlimit>,
+    /// EM's policy if not given
+    #[serde(default)]
+    pub scheduling_policy: Option<SchedulingPolicy>,
+    /// 1 ~ 99 for FIFO and RR, 0 for OTHER, the lowest if not given
+    #[serde(default)]
+    pub scheduling_priority: Option<i32>,
+    /// bit mask of cpu cores, e.g. 0xc for core 2 and 3
+    #[serde(default)]
+    pub cpu_affinity: Option<u64>,
+    /// stdout and stderr, forwarded to EM's log if not given
+    #[serde(default)]
+    pub log: Log,
+    /// file the manifest is loaded from, None if it's not loaded by `from_file`
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
 }
 
 impl ExecutionManifest {
     pub fn from(contents: &str) -> Result<Self> {
-        let manifest: ExecutionManifest = serde_yaml::from_str(&contents)?;
+        let manifest: ExecutionManifest = serde_yaml::from_str(contents)?;
         Ok(manifest)
     }
 
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let mut file = std::fs::File::open(path)?;
+        let mut file = std::fs::File::open(path.as_ref())?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
-        ExecutionManifest::from(&contents)
+        let mut manifest = ExecutionManifest::from(&contents)?;
+        manifest.source = Some(path.as_ref().to_path_buf());
+        Ok(manifest)
     }
 
     pub fn validate(&self, machine_manifest: &MachineManifest) -> Result<()> {
         // check app-dependency
         for dependency in &self.app_dependency {
-            match dependency.split_once('.') {
-                Some((_name, mode)) => {
-                    if !machine_manifest.process_mode.contains(&mode.to_string()) {
-                        return Err(ExecutionManifestError::InvalidApplicationDependencyMode(
-                            dependency.clone(),
+            if !machine_manifest.process_mode.contains(&dependency.state) {
+                return Err(ExecutionManifestError::InvalidApplicationDependencyMode(
+                    dependency.to_string(),
+                    self.name.clone(),
+                )
+                .into());
+            }
+        }
+
+        // check mode-dependency
+        for dependency in &self.mode_dependency {
+            match machine_manifest
+                .function_group_set
+                .get(&dependency.function_group)
+            {
+                Some(fg_mode) => {
+                    if !fg_mode.mode.contains(&dependency.state) {
+                        return Err(ExecutionManifestError::NoModeInFG(
+                            dependency.to_string(),
                             self.name.clone(),
                         )
                         .into());
                     }
                 }
                 None => {
-                    return Err(ExecutionManifestError::InvalidApplicationDependencyFormat(
-                        dependency.clone(),
+                    return Err(ExecutionManifestError::FGNotExist(
+                        dependency.function_group.clone(),
                         self.name.clone(),
                     )
                     .into());
@@ -85,36 +245,85 @@ impl ExecutionManifest {
             }
         }
 
-        // check mode-dependency
-        for dependency in &self.mode_dependency {
-            match dependency.split_once('.') {
-                Some((fg, mode)) => {
-                    let fg = fg.to_string();
-                    if machine_manifest.function_group_set.contains_key(&fg) {
-                        // println!(
-                        //     "{} ==> {:?}",
-                        //     fg,
-                        //     machine_manifest.function_group_set.get(&fg)
-                        // );
-                        if let Some(fg_mode) = machine_manifest.function_group_set.get(&fg) {
-                            let mode = mode.to_string();
-                            if !fg_mode.mode.contains(&mode) {
-                                return Err(ExecutionManifestError::NoModeInFG(
-                                    dependency.clone(),
-                                    self.name.clone(),
-                                )
-                                .into());
-                            }
-                        }
-                    } else {
-                        return Err(
-                            ExecutionManifestError::FGNotExist(fg, self.name.clone()).into()
-                        );
-                    }
-                }
-                None => {
-                    return Err(ExecutionManifestError::InvalidModeDependencyFormat(
-                        dependency.clone(),
+        // check resource limits
+        if let Some(resource_group) = &self.resource_group {
+            if !machine_manifest.resource_group.contains_key(resource_group) {
+                return Err(ExecutionManifestError::ResourceGroupNotExist(
+                    resource_group.clone(),
+                    self.name.clone(),
+                )
+                .into());
+            }
+        }
+        for (resource, rlimit) in &self.rlimit {
+            if rlimit.soft > rlimit.hard {
+                return Err(
+                    ExecutionManifestError::InvalidRlimit(*resource, self.name.clone()).into(),
+                );
+            }
+        }
+
+        // check scheduling
+        if let Some(priority) = self.scheduling_priority {
+            let policy = self.scheduling_policy.unwrap_or_default();
+            if !policy.priority_range().contains(&priority) {
+                return Err(ExecutionManifestError::InvalidSchedulingPriority(
+                    priority,
+                    policy,
+                    self.name.clone(),
+                )
+                .into());
+            }
+        }
+        if self.cpu_affinity == Some(0) {
+            return Err(ExecutionManifestError::EmptyCpuAffinity(self.name.clone()).into());
+        }
+
+        if self.log.max_size == 0 {
+            return Err(ExecutionManifestError::ZeroLogMaxSize(self.name.clone()).into());
+        }
+
+        self.validate_credential()
+    }
+
+    fn validate_credential(&self) -> Result<()> {
+        if let Some(user) = &self.user {
+            if user.resolve_user().is_none() {
+                return Err(
+                    ExecutionManifestError::UnknownUser(user.to_string(), self.name.clone()).into(),
+                );
+            }
+        }
+        for group in self.group.iter().chain(&self.supplementary_groups) {
+            if group.resolve_group().is_none() {
+                return Err(
+                    ExecutionManifestError::UnknownGroup(group.to_string(), self.name.clone())
+                        .into(),
+                );
+            }
+        }
+
+        let Some(capability) = &self.capability else {
+            return Ok(());
+        };
+        let bounding = capability.bounding.iter().flatten();
+        for name in bounding.clone().chain(&capability.ambient) {
+            if capability_number(name).is_none() {
+                return Err(
+                    ExecutionManifestError::UnknownCapability(name.clone(), self.name.clone())
+                        .into(),
+                );
+            }
+        }
+        // ambient capabilities are dropped if they are not bounded
+        if capability.bounding.is_some() {
+            for name in &capability.ambient {
+                if !bounding
+                    .clone()
+                    .any(|bounded| capability_number(bounded) == capability_number(name))
+                {
+                    return Err(ExecutionManifestError::AmbientCapabilityNotBounded(
+                        name.clone(),
                         self.name.clone(),
                     )
                     .into());
@@ -135,6 +344,7 @@ mod tests {
     fn serialize() {
         let execution_manifest_str = r#"
             name: SM
+            executable: sm           # relative to the root where the manifest is found
             environmental_variable:
               ENV1: "environment variable smaple1"
               ENV2: "environment variable smaple2"
@@ -145,12 +355,34 @@ mod tests {
               enter: 1          # 1 second
               exit: 1           # 1 second
             reporting_behavior: true # true or false
-            number_of_restart: 0     # really dont' know it is necessary
+            number_of_restart: 2     # restarts on unexpected termination
+            restart_backoff: 100     # 100 ms before the first restart, doubled for the next
             app_dependency:
               - UCM.Running
               - APP.Running
             mode_dependency:
               - MachineFG.Startup
+            execution_error: 3       # reported to SM on unexpected termination
+            user: sm                 # uid or user name
+            group: 1000              # gid or group name
+            supplementary_groups:
+              - dialout
+            capability:
+              bounding:
+                - CAP_NET_BIND_SERVICE
+              ambient:
+                - CAP_NET_BIND_SERVICE
+            resource_group: RG1
+            rlimit:
+              NOFILE:
+                soft: 1024
+                hard: 4096
+            scheduling_policy: FIFO
+            scheduling_priority: 50
+            cpu_affinity: 0xc        # core 2 and 3
+            log:
+              output: file
+              max_size: 65536
         "#;
 
         let execution_manifest = ExecutionManifest::from(execution_manifest_str).unwrap();
@@ -158,6 +390,7 @@ mod tests {
             execution_manifest,
             ExecutionManifest {
                 name: String::from("SM"),
+                executable: Some(String::from("sm")),
                 environmental_variable: {
                     let mut map = HashMap::new();
                     map.insert(
@@ -178,9 +411,36 @@ mod tests {
                 },
                 enter_exit_timeout: Some(EnterExitTimeout { enter: 1, exit: 1 }),
                 reporting_behavior: true,
-                number_of_restart: 0,
-                app_dependency: vec![String::from("UCM.Running"), String::from("APP.Running"),],
-                mode_dependency: vec![String::from("MachineFG.Startup"),],
+                number_of_restart: 2,
+                restart_backoff: 100,
+                app_dependency: vec![
+                    AppDependency::new("UCM", "Running"),
+                    AppDependency::new("APP", "Running"),
+                ],
+                mode_dependency: vec![ModeDependency::new("MachineFG", "Startup")],
+                execution_error: Some(3),
+                user: Some(Identity::Name(String::from("sm"))),
+                group: Some(Identity::Id(1000)),
+                supplementary_groups: vec![Identity::Name(String::from("dialout"))],
+                capability: Some(Capability {
+                    bounding: Some(vec![String::from("CAP_NET_BIND_SERVICE")]),
+                    ambient: vec![String::from("CAP_NET_BIND_SERVICE")],
+                }),
+                resource_group: Some(String::from("RG1")),
+                rlimit: {
+                    let mut map = HashMap::new();
+                    map.insert(RlimitResource::Nofile, Rlimit { soft: 1024, hard: 4096 });
+                    map
+                },
+                scheduling_policy: Some(SchedulingPolicy::Fifo),
+                scheduling_priority: Some(50),
+                cpu_affinity: Some(0xc),
+                log: Log {
+                    output: LogOutput::File,
+                    max_size: 65536,
+                    max_files: 3,
+                },
+                source: None,
             }
         )
     }
@@ -200,25 +460,31 @@ mod tests {
             name: TestApp
         "#;
 
-        let mut execution_manifest = ExecutionManifest::from(&execution_manifest_str).unwrap();
+        let mut execution_manifest = ExecutionManifest::from(execution_manifest_str).unwrap();
         let machine_manifest = MachineManifest::from("").unwrap();
         execution_manifest.name = "TestApp".to_owned();
-        execution_manifest.app_dependency =
-            vec!["APP1.Running".to_owned(), "APP2.Terminated".to_owned()];
+        execution_manifest.app_dependency = vec![
+            AppDependency::new("APP1", "Running"),
+            AppDependency::new("APP2", "Terminated"),
+        ];
         assert!(execution_manifest.validate(&machine_manifest).is_ok());
 
-        // InvalidApplicationDependencyFormat
-        execution_manifest.app_dependency =
-            vec!["APP1Running".to_owned(), "APP2.Terminated".to_owned()];
-        let validate = execution_manifest.validate(&machine_manifest);
-        assert_eq!(
-            validate.err().map(|e| e.to_string()).unwrap(),
-            String::from("Inavlid application dependency format: APP1Running for TestApp"),
-        );
+        // InvalidApplicationDependencyFormat, on load
+        let execution_manifest_str = r#"
+            name: TestApp
+            app_dependency:
+              - APP1Running
+        "#;
+        let error = ExecutionManifest::from(execution_manifest_str).err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("Invalid application dependency format: APP1Running"));
 
         // InvalidApplicationDependencyMode
-        execution_manifest.app_dependency =
-            vec!["APP1.Running".to_owned(), "APP2.Terminating".to_owned()];
+        execution_manifest.app_dependency = vec![
+            AppDependency::new("APP1", "Running"),
+            AppDependency::new("APP2", "Terminating"),
+        ];
         let validate = execution_manifest.validate(&machine_manifest);
         assert_eq!(
             validate.err().map(|e| e.to_string()).unwrap(),
@@ -232,23 +498,26 @@ mod tests {
             name: TestApp
         "#;
 
-        let mut execution_manifest = ExecutionManifest::from(&execution_manifest_str).unwrap();
+        let mut execution_manifest = ExecutionManifest::from(execution_manifest_str).unwrap();
         let mut machine_manifest = MachineManifest::from("").unwrap();
 
         execution_manifest.name = "TestApp".to_owned();
-        execution_manifest.mode_dependency = vec!["MachineFG.Startup".to_owned()];
+        execution_manifest.mode_dependency = vec![ModeDependency::new("MachineFG", "Startup")];
         assert!(execution_manifest.validate(&machine_manifest).is_ok());
 
-        // InvalidModeDependencyFormat
-        execution_manifest.mode_dependency = vec!["MachineFGStartup".to_owned()];
-        let validate = execution_manifest.validate(&machine_manifest);
-        assert_eq!(
-            validate.err().map(|e| e.to_string()).unwrap(),
-            String::from("Inavlid mode dependency format: MachineFGStartup for TestApp"),
-        );
+        // InvalidModeDependencyFormat, on load
+        let execution_manifest_str = r#"
+            name: TestApp
+            mode_dependency:
+              - MachineFGStartup
+        "#;
+        let error = ExecutionManifest::from(execution_manifest_str).err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("Invalid mode dependency format: MachineFGStartup"));
 
         // FGNotExist
-        execution_manifest.mode_dependency = vec!["FG1.On".to_owned()];
+        execution_manifest.mode_dependency = vec![ModeDependency::new("FG1", "On")];
         let validate = execution_manifest.validate(&machine_manifest);
         assert_eq!(
             validate.err().map(|e| e.to_string()).unwrap(),
@@ -256,12 +525,13 @@ mod tests {
         );
 
         // EmptyModeInFg
-        execution_manifest.mode_dependency = vec!["FG1.On".to_owned()];
+        execution_manifest.mode_dependency = vec![ModeDependency::new("FG1", "On")];
         machine_manifest.function_group_set.insert(
             "FG1".to_owned(),
             FunctionGroupMode {
                 initial_mode: "FG1".to_owned(),
                 mode: vec![],
+                transition: None,
             },
         );
         let validate = execution_manifest.validate(&machine_manifest);
@@ -270,6 +540,125 @@ mod tests {
             String::from("No mode(FG1.On) for TestApp"),
         );
     }
+
+    #[test]
+    fn credential_validate() {
+        let mut execution_manifest = ExecutionManifest::from("name: TestApp").unwrap();
+        let machine_manifest = MachineManifest::from("").unwrap();
+
+        execution_manifest.user = Some(Identity::Name("root".to_owned()));
+        execution_manifest.supplementary_groups = vec![Identity::Name("root".to_owned())];
+        execution_manifest.capability = Some(Capability {
+            bounding: Some(vec!["CAP_NET_BIND_SERVICE".to_owned(), "CAP_KILL".to_owned()]),
+            ambient: vec!["CAP_KILL".to_owned()],
+        });
+        assert!(execution_manifest.validate(&machine_manifest).is_ok());
+
+        // UnknownUser
+        execution_manifest.user = Some(Identity::Name("no-such-user".to_owned()));
+        let validate = execution_manifest.validate(&machine_manifest);
+        assert_eq!(
+            validate.err().map(|e| e.to_string()).unwrap(),
+            String::from("Unknown user: no-such-user for TestApp"),
+        );
+        execution_manifest.user = None;
+
+        // UnknownGroup
+        execution_manifest.group = Some(Identity::Name("no-such-group".to_owned()));
+        let validate = execution_manifest.validate(&machine_manifest);
+        assert_eq!(
+            validate.err().map(|e| e.to_string()).unwrap(),
+            String::from("Unknown group: no-such-group for TestApp"),
+        );
+        execution_manifest.group = None;
+
+        // UnknownCapability
+        execution_manifest.capability = Some(Capability {
+            bounding: None,
+            ambient: vec!["CAP_FLY".to_owned()],
+        });
+        let validate = execution_manifest.validate(&machine_manifest);
+        assert_eq!(
+            validate.err().map(|e| e.to_string()).unwrap(),
+            String::from("Unknown capability: CAP_FLY for TestApp"),
+        );
+
+        // AmbientCapabilityNotBounded
+        execution_manifest.capability = Some(Capability {
+            bounding: Some(vec!["CAP_KILL".to_owned()]),
+            ambient: vec!["CAP_NET_RAW".to_owned()],
+        });
+        let validate = execution_manifest.validate(&machine_manifest);
+        assert_eq!(
+            validate.err().map(|e| e.to_string()).unwrap(),
+            String::from("Ambient capability(CAP_NET_RAW) is not in the bounding set for TestApp"),
+        );
+    }
+
+    #[test]
+    fn resource_validate() {
+        let mut execution_manifest = ExecutionManifest::from("name: TestApp").unwrap();
+        let mut machine_manifest = MachineManifest::from("").unwrap();
+
+        // ResourceGroupNotExist
+        execution_manifest.resource_group = Some("RG1".to_owned());
+        let validate = execution_manifest.validate(&machine_manifest);
+        assert_eq!(
+            validate.err().map(|e| e.to_string()).unwrap(),
+            String::from("Resource group(RG1) doesn't exist for TestApp"),
+        );
+
+        machine_manifest
+            .resource_group
+            .insert("RG1".to_owned(), Default::default());
+        assert!(execution_manifest.validate(&machine_manifest).is_ok());
+
+        // InvalidRlimit
+        execution_manifest
+            .rlimit
+            .insert(RlimitResource::Nofile, Rlimit { soft: 2, hard: 1 });
+        let validate = execution_manifest.validate(&machine_manifest);
+        assert_eq!(
+            validate.err().map(|e| e.to_string()).unwrap(),
+            String::from("Soft limit is greater than hard limit of Nofile for TestApp"),
+        );
+    }
+
+    #[test]
+    fn scheduling_validate() {
+        let mut execution_manifest = ExecutionManifest::from("name: TestApp").unwrap();
+        let machine_manifest = MachineManifest::from("").unwrap();
+
+        execution_manifest.scheduling_policy = Some(SchedulingPolicy::Rr);
+        execution_manifest.scheduling_priority = Some(99);
+        execution_manifest.cpu_affinity = Some(1);
+        assert!(execution_manifest.validate(&machine_manifest).is_ok());
+
+        // InvalidSchedulingPriority, real-time priority under OTHER
+        execution_manifest.scheduling_policy = None;
+        let validate = execution_manifest.validate(&machine_manifest);
+        assert_eq!(
+            validate.err().map(|e| e.to_string()).unwrap(),
+            String::from("Invalid scheduling priority(99) under Other for TestApp"),
+        );
+
+        execution_manifest.scheduling_policy = Some(SchedulingPolicy::Fifo);
+        execution_manifest.scheduling_priority = Some(0);
+        let validate = execution_manifest.validate(&machine_manifest);
+        assert_eq!(
+            validate.err().map(|e| e.to_string()).unwrap(),
+            String::from("Invalid scheduling priority(0) under Fifo for TestApp"),
+        );
+
+        // EmptyCpuAffinity
+        execution_manifest.scheduling_priority = None;
+        execution_manifest.cpu_affinity = Some(0);
+        let validate = execution_manifest.validate(&machine_manifest);
+        assert_eq!(
+            validate.err().map(|e| e.to_string()).unwrap(),
+            String::from("Empty cpu affinity for TestApp"),
+        );
+    }
 }
 
 /*
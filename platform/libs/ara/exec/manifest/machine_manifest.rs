@@ -8,13 +8,12 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+pub use crate::function_group::{MACHINE_FG, OFF, RESTART, SHUTDOWN, STARTUP};
+
 pub const RUNNING: &str = "Running";
 pub const TERMINATED: &str = "Terminated";
-pub const MACHINE_FG: &str = "MachineFG";
-pub const STARTUP: &str = "Startup";
-pub const SHUTDOWN: &str = "Shutdown";
-pub const RESTART: &str = "Restart";
-pub const OFF: &str = "Off";
+/// wildcard of a transition for any mode of the function group
+pub const ANY_MODE: &str = "*";
 
 #[derive(Debug, Error)]
 enum MachineManifestError {
@@ -28,12 +27,93 @@ enum MachineManifestError {
     InvalidFGInitialMode(String, String),
     #[error("Invalid mode({0}) for {1}")]
     InvalidFGMode(String, String),
+    #[error("Invalid resource group({0}) : {1}")]
+    InvalidResourceGroup(String, String),
+    #[error("Undeclared mode({0}) in the transition for {1}")]
+    UndeclaredTransitionMode(String, String),
+}
+
+/// allowed change of the function group state, `from` or `to` is `ANY_MODE` for any mode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionGroupTransition {
+    pub from: String,
+    pub to: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FunctionGroupMode {
     pub initial_mode: String,
     pub mode: Vec<String>,
+    /// allowed transitions, every transition is allowed if not given
+    #[serde(default)]
+    pub transition: Option<Vec<FunctionGroupTransition>>,
+}
+
+impl FunctionGroupMode {
+    /// Whether the function group may go from `from` to `to`
+    /// Staying in the same mode is not a transition, and always allowed.
+    pub fn allows(&self, from: &str, to: &str) -> bool {
+        let matches = |pattern: &str, mode: &str| pattern == ANY_MODE || pattern == mode;
+        from == to
+            || self.transition.as_ref().is_none_or(|transitions| {
+                transitions.iter().any(|transition| {
+                    matches(&transition.from, from) && matches(&transition.to, to)
+                })
+            })
+    }
+
+    fn validate(&self, name: &str) -> Result<()> {
+        for transition in self.transition.iter().flatten() {
+            for mode in [&transition.from, &transition.to] {
+                if mode != ANY_MODE && !self.mode.contains(mode) {
+                    return Err(MachineManifestError::UndeclaredTransitionMode(
+                        mode.clone(),
+                        name.to_owned(),
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Class ResourceGroup
+/// Package M2::AUTOSARTemplates::AdaptivePlatform::MachineManifest
+/// Note This meta-class represents a resource group that limits the resource usage of a collection
+/// of processes. It is a cgroup v2 of the same name under the cgroup root of EM.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResourceGroup {
+    /// cpu.weight, 1 ~ 10000
+    #[serde(default)]
+    pub cpu_weight: Option<u32>,
+    /// cpu.max, percent of a cpu core, e.g. 150 for one and a half cores
+    #[serde(default)]
+    pub cpu_quota: Option<u32>,
+    /// memory.max in bytes
+    #[serde(default)]
+    pub memory_max: Option<u64>,
+    /// pids.max
+    #[serde(default)]
+    pub pids_max: Option<u32>,
+}
+
+impl ResourceGroup {
+    fn validate(&self, name: &str) -> Result<()> {
+        let invalid = |reason: &str| {
+            MachineManifestError::InvalidResourceGroup(name.to_owned(), reason.to_owned())
+        };
+        if self.cpu_weight.is_some_and(|weight| !(1..=10000).contains(&weight)) {
+            return Err(invalid("cpu_weight has to be 1 ~ 10000").into());
+        }
+        if self.cpu_quota == Some(0) {
+            return Err(invalid("cpu_quota has to be positive").into());
+        }
+        if self.pids_max == Some(0) {
+            return Err(invalid("pids_max has to be positive").into());
+        }
+        Ok(())
+    }
 }
 
 fn default_process_mode() -> Vec<String> {
@@ -47,6 +127,7 @@ fn default_function_group_set() -> HashMap<String, FunctionGroupMode> {
         FunctionGroupMode {
             initial_mode: STARTUP.to_owned(),
             mode: vec![STARTUP.to_owned(), SHUTDOWN.to_owned(), RESTART.to_owned()],
+            transition: None,
         },
     );
     set
@@ -62,15 +143,18 @@ pub struct MachineManifest {
     pub process_mode: Vec<String>,
     #[serde(default = "default_function_group_set")]
     pub function_group_set: HashMap<String, FunctionGroupMode>,
+    /// resource group name / limits
+    #[serde(default)]
+    pub resource_group: HashMap<String, ResourceGroup>,
 }
 
 impl MachineManifest {
     pub fn from(contents: &str) -> Result<Self> {
-        let manifest: MachineManifest = serde_yaml::from_str(&contents)?;
+        let manifest: MachineManifest = serde_yaml::from_str(contents)?;
 
         // check validation
-        let default_process_mode = vec![RUNNING.to_owned(), TERMINATED.to_owned()];
-        let default_machine_fg = vec![STARTUP.to_owned(), SHUTDOWN.to_owned(), RESTART.to_owned()];
+        let default_process_mode = [RUNNING.to_owned(), TERMINATED.to_owned()];
+        let default_machine_fg = [STARTUP.to_owned(), SHUTDOWN.to_owned(), RESTART.to_owned()];
 
         // process
         if manifest.process_mode.is_empty() {
@@ -118,6 +202,11 @@ impl MachineManifest {
                     return Err(MachineManifestError::InvalidFGMode(mode, name.clone()).into());
                 }
             }
+            fg.validate(name)?;
+        }
+
+        for (name, resource_group) in manifest.resource_group.iter() {
+            resource_group.validate(name)?;
         }
 
         Ok(manifest)
@@ -158,11 +247,25 @@ mod tests {
                 mode:
                   - "Off"
                   - "On"
+                  - "Verify"
+                transition:         # every transition is allowed if omits
+                  - from: "Off"
+                    to: "On"
+                  - from: "On"
+                    to: "Verify"
+                  - from: "*"       # any mode
+                    to: "Off"
               FG2:
                 initial_mode: "Off"
                 mode:
                   - "Off"
                   - "On"
+            resource_group:
+              RG1:
+                cpu_weight: 200
+                cpu_quota: 50
+                memory_max: 1048576
+                pids_max: 16
             # ...
         "#;
 
@@ -199,13 +302,27 @@ mod tests {
                                 String::from(SHUTDOWN),
                                 String::from(RESTART),
                             ],
+                            transition: None,
                         },
                     );
+                    let transition = |from: &str, to: &str| FunctionGroupTransition {
+                        from: String::from(from),
+                        to: String::from(to),
+                    };
                     set.insert(
                         String::from("FG1"),
                         FunctionGroupMode {
                             initial_mode: String::from(OFF),
-                            mode: vec![String::from(OFF), String::from("On")],
+                            mode: vec![
+                                String::from(OFF),
+                                String::from("On"),
+                                String::from("Verify"),
+                            ],
+                            transition: Some(vec![
+                                transition(OFF, "On"),
+                                transition("On", "Verify"),
+                                transition(ANY_MODE, OFF),
+                            ]),
                         },
                     );
                     set.insert(
@@ -213,10 +330,24 @@ mod tests {
                         FunctionGroupMode {
                             initial_mode: String::from(OFF),
                             mode: vec![String::from(OFF), String::from("On")],
+                            transition: None,
                         },
                     );
                     set
-                }
+                },
+                resource_group: {
+                    let mut resource_group = HashMap::new();
+                    resource_group.insert(
+                        String::from("RG1"),
+                        ResourceGroup {
+                            cpu_weight: Some(200),
+                            cpu_quota: Some(50),
+                            memory_max: Some(1048576),
+                            pids_max: Some(16),
+                        },
+                    );
+                    resource_group
+                },
             }
         );
     }
@@ -244,14 +375,94 @@ mod tests {
                                 String::from(SHUTDOWN),
                                 String::from(RESTART),
                             ],
+                            transition: None,
                         },
                     );
                     set
-                }
+                },
+                resource_group: HashMap::new(),
             }
         );
     }
 
+    #[test]
+    fn transition() {
+        let machine_manifest_str = r#"
+            function_group_set:
+              FG1:
+                initial_mode: "Off"
+                mode:
+                  - "Off"
+                  - "On"
+                  - "Verify"
+                transition:
+                  - from: "Off"
+                    to: "On"
+                  - from: "On"
+                    to: "Verify"
+                  - from: "*"
+                    to: "Off"
+              MachineFG:
+                initial_mode: "Startup"
+                mode:
+                  - "Startup"
+                  - "Shutdown"
+                  - "Restart"
+        "#;
+        let machine_manifest = MachineManifest::from(machine_manifest_str).unwrap();
+        let fg1 = machine_manifest.function_group_set.get("FG1").unwrap();
+        assert!(fg1.allows("Off", "On"));
+        assert!(fg1.allows("On", "Verify"));
+        assert!(fg1.allows("Verify", "Off"));
+        assert!(fg1.allows("On", "On"));
+        assert!(!fg1.allows("Off", "Verify"));
+        assert!(!fg1.allows("Verify", "On"));
+
+        // no transition table
+        let machine_fg = machine_manifest.function_group_set.get(MACHINE_FG).unwrap();
+        assert!(machine_fg.allows(STARTUP, SHUTDOWN));
+
+        // UndeclaredTransitionMode
+        let machine_manifest_str = machine_manifest_str.replace("to: \"Off\"", "to: \"Of\"");
+        assert_eq!(
+            MachineManifest::from(&machine_manifest_str)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Undeclared mode(Of) in the transition for FG1"
+        );
+    }
+
+    #[test]
+    fn machine_fg_state() {
+        // the state SM and EM start with is the one of the manifest
+        let machine_manifest = MachineManifest::from("").unwrap();
+        let startup = crate::function_group::get_machine_fg_state(STARTUP);
+        let machine_fg = machine_manifest
+            .function_group_set
+            .get(&startup.function_group)
+            .unwrap();
+        assert!(machine_fg.mode.contains(&startup.function_group_state));
+
+        // a mismatched name is an error on load
+        let machine_manifest_str = r#"
+            function_group_set:
+              MachineFg:
+                initial_mode: "Startup"
+                mode:
+                  - "Startup"
+                  - "Shutdown"
+                  - "Restart"
+        "#;
+        assert_eq!(
+            MachineManifest::from(machine_manifest_str)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Empty MachineFG"
+        );
+    }
+
     #[test]
     fn invalid_process_mode() {
         let machine_manifest_str = r#"
@@ -346,6 +557,33 @@ mod tests {
             String::from("Invalid mode(Ready,Go) for FG1"),
         );
     }
+
+    #[test]
+    fn invalid_resource_group() {
+        let invalid_cpu_weight = r#"
+            resource_group:
+              RG1:
+                cpu_weight: 0
+        "#;
+
+        let manifest = MachineManifest::from(invalid_cpu_weight);
+        assert_eq!(
+            manifest.err().map(|e| e.to_string()).unwrap(),
+            String::from("Invalid resource group(RG1) : cpu_weight has to be 1 ~ 10000"),
+        );
+
+        let invalid_pids_max = r#"
+            resource_group:
+              RG1:
+                pids_max: 0
+        "#;
+
+        let manifest = MachineManifest::from(invalid_pids_max);
+        assert_eq!(
+            manifest.err().map(|e| e.to_string()).unwrap(),
+            String::from("Invalid resource group(RG1) : pids_max has to be positive"),
+        );
+    }
 }
 
 /*
@@ -1,35 +1,51 @@
-use lazy_static::lazy_static;
-use std::path::Path;
-use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::UnixStream;
-use tokio::sync::Mutex;
-use thiserror::Error;
-use crate::function_group::FunctionGroupState;
+use crate::codec::{read_frame, write_frame};
+use crate::execution_client::ExecutionErrorEvent;
+use crate::function_group::{FunctionGroup, FunctionGroupState};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use tokio::time::{timeout, Duration};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::UnixStream;
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Duration};
 
-pub const OARA_SM_DOMAIN_SOCKET: &'static str = "/tmp/oara_sm_domain_socket";
+pub const OARA_SM_DOMAIN_SOCKET: &str = "/tmp/oara_sm_domain_socket";
+
+/// SetState is answered when the whole transition is done, e.g. after the enter timeouts
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SmClientCommand {
     GetInitialState,
     SetState(FunctionGroupState),
+    GetExecutionError(FunctionGroup),
+    GetProcessStatus(String),
     // TBD
 }
 
+/// Several requests can be in flight on a connection, `id` correlates the response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmRequest {
+    pub id: u64,
+    pub command: SmClientCommand,
+}
+
 #[derive(Debug, Clone, Error, Eq, PartialEq, Serialize, Deserialize)]
 pub enum InitialStateError {
-    #[error("Failed to change state to MachineFg.Startup")]
+    #[error("Failed to change state to MachineFG.Startup")]
     FailedInitializeInitialState,
     #[error("can’t communicate with Execution Management")]
     CommunicationError,
 }
 
-#[derive(Debug, Clone, Error, Serialize, Deserialize)]
+#[derive(Debug, Clone, Error, Eq, PartialEq, Serialize, Deserialize)]
 pub enum SetStateError {
-    // not understand this requirement which means to call SetState from multi-thread or multi-process
+    // a newer SetState for the same Function Group was requested before this one completed
     #[error("cancelled by a newer request")]
     Canceled,
     #[error("transition to the requested Function Group state failed")]
@@ -48,381 +64,280 @@ pub enum SetStateError {
     MetamodelError,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum SmResponse {
-    GetInitialState(Result<(), InitialStateError>),
-    SetState(Result<(), SetStateError>),
+#[derive(Debug, Clone, Error, Eq, PartialEq, Serialize, Deserialize)]
+pub enum GetExecutionErrorError {
+    #[error("The Function Group is not in an Undefined Function Group State")]
+    Failed,
+    #[error("can’t communicate with Execution Management")]
+    CommunicationError,
+    #[error("The given Function Group couldn’t be found in the ProcessedManifest")]
+    MetamodelError,
 }
 
-lazy_static! {
-    static ref STATE_CLIENT: Arc<Mutex<StateClient>> = Arc::new(Mutex::new(StateClient::new()));
+#[derive(Debug, Clone, Error, Eq, PartialEq, Serialize, Deserialize)]
+pub enum GetProcessStatusError {
+    #[error("can’t communicate with Execution Management")]
+    CommunicationError,
+    #[error("The given Process couldn’t be found in the ProcessedManifest")]
+    MetamodelError,
 }
 
-#[allow(unused)]    // FIXME
-async fn connect<P>(path: Option<P>)
-where
-    P: AsRef<Path>,
-{
-    if path.is_none() {
-        STATE_CLIENT.lock().await.connect(&OARA_SM_DOMAIN_SOCKET);
-    } else {
-        STATE_CLIENT.lock().await.connect(path.unwrap());
-    }
+/// Status of a process managed by EM
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ProcessStatus {
+    pub process: String,
+    /// None unless the process is launched
+    pub pid: Option<i32>,
+    /// restarts after unexpected terminations since the last successful transition
+    pub restart_count: u32,
 }
 
-#[allow(unused)]    // FIXME
-async fn state_client() -> Arc<Mutex<StateClient>> {
-    STATE_CLIENT.clone()
+#[derive(Debug, Clone, Error, Eq, PartialEq)]
+pub enum StateClientError {
+    #[error("can’t communicate with Execution Management")]
+    CommunicationError,
 }
 
-#[derive(Debug)]
-struct StateClient {
-    //undefined_state_callback: Box<dyn Fn(ExecutionClientError)>   // TBD
-    socket: Option<UnixStream>,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SmResponse {
+    GetInitialState(Result<(), InitialStateError>),
+    SetState(Result<(), SetStateError>),
+    GetExecutionError(Result<ExecutionErrorEvent, GetExecutionErrorError>),
+    GetProcessStatus(Result<ProcessStatus, GetProcessStatusError>),
 }
 
-impl StateClient {
-    fn new() -> Self {
-        Self { socket: None }
-    }
-
-    async fn connect<P>(&mut self, path: P) -> Result<()>
-    where
-        P: AsRef<Path>,
-    {
-        assert!(self.socket.is_none());
-        match UnixStream::connect(path).await {
-            Ok(stream) => {
-                self.socket = Some(stream);
-                Ok(())
-            }
-            Err(error) => Err(error.into()),
-        }
-        //self.socket = Some(UnixStream::connect(OARA_SM_DOMAIN_SOCKET).await?);
-        //let x = UnixStream::connect(OARA_SM_DOMAIN_SOCKET).await;
-        //println!("--==============-> {:?}", x);
-        //self.socket = x.unwrap();
-    }
+/// Message from EM to StateClient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SmMessage {
+    Response { id: u64, response: SmResponse },
+    /// a Function Group changed to the Undefined Function Group State
+    ExecutionErrorEvent(ExecutionErrorEvent),
+}
 
-    /// ara::exec::ExecErrc::kCancelled
-    ///   StateManagement may decide to cancel SWS_EM_01023
-    ///   transition and start specific startup sequence. This could happen
-    ///   for number of reasons and one of them could be interrupted Machine update sequence.
-    /// ara::exec::ExecErrc::kFailed
-    ///   if transition to the requested Function Group state failed
-    /// ara::exec::ExecErrc::kCommunicationError
-    ///   if StateClient can’t communicate with Execution Management (e.g.IPC link is down)
+/// How StateClient (re)connects to EM, e.g. while EM restarts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// at least one attempt is made
+    pub max_attempts: u32,
+    pub interval: Duration,
+}
 
-    #[allow(unused)]
-    pub async fn get_initial_machine_state_transition_result(&mut self) -> Result<()> {
-        assert!(self.socket.is_some());
-        if let Some(socket) = self.socket.as_mut() {
-            // serialze command
-            let command = SmClientCommand::GetInitialState;
-            let encoded_command = bincode::serialize(&command)?;
-            socket.write_all(&encoded_command).await?;
-
-            // wait the result from server
-            let mut buffer = vec![0; 1024];
-
-            match timeout(Duration::from_secs(1), socket.read(&mut buffer)).await {
-                Ok(Ok(n)) => {
-                    let response: SmResponse = bincode::deserialize(&buffer[..n])?;
-                    match response {
-                        SmResponse::GetInitialState(response) => match response {
-                            Ok(_) => {}
-                            Err(error) => {
-                                return Err(error.into());
-                            }
-                        },
-                        _ => {
-                            panic!("Invalid response for GetInitialState");
-                        }
-                    }
-                }
-                Ok(Err(_)) => {
-                    // error on read
-                    return Err(InitialStateError::CommunicationError.into());
-                }
-                Err(_) => {
-                    // timeout
-                    return Err(InitialStateError::CommunicationError.into());
-                }
-            }
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            interval: Duration::from_millis(200),
         }
-
-        Ok(())
     }
+}
 
-    /// [SWS_EM_02278] Definition of API function ara::exec::StateClient::SetState
-    /// Syntax: ara::core::Future< void > SetState (const FunctionGroupState &state) const noexcept;
-    /// Parameters (in): state representing meta-model definition of a state inside a specific
-    /// Function Group. Execution Management will perform state
-    /// transition from the current state to the state identified by this parameter.
-    /// Return value: ara::core::Future< void > void if requested transition is successful, otherwise it returns Exec
-    /// ErrorDomain error.
-    /// Errors:
-    /// ara::exec::ExecErrc::kCancelled
-    ///   if transition to the requested Function Group state was cancelled by a newer request
-    /// ara::exec::ExecErrc::kFailed
-    ///   if transition to the requested Function Group state failed
-    /// ara::exec::ExecErrc::kFailedUnexpectedTerminationOnEnter
-    ///   if Unexpected Termination in Process of target Function Group State happened.
-    /// ara::exec::ExecErrc::kCommunicationError
-    ///   if StateClient can’t communicate with Execution Management (e.g./// IPC link is down)
-    /// ara::exec::ExecErrc::kInvalidTransition
-    ///   if transition to the requested state is prohibited (e.g. Off state for
-    ///   MachineFG) or the requested Function Group State is invalid (e.g.
-    ///   does not exist anymore after a software update)
-    /// ara::exec::ExecErrc::kIntegrityOrAuthenticity
-    ///   if an integrity or authenticity check failed during state transition
-    /// ara::exec::ExecErrc::kFailedUnexpectedTermination
-    ///   One of the processes terminated in an unexpected way during the state transition.
-    /// ara::exec::ExecErrc::kMetaModelError
-    ///   The given Function Group State couldn’t be found in the ProcessedManifest.
-    /// Description: Method to request state transition for a single Function Group.
-    /// This method will request Execution Management to perform state transition and return
-    /// immediately. Returned ara::core::Future can be used to determine result of requested transition.
-    #[allow(unused)]
-    pub async fn set_state(&mut self, state: &FunctionGroupState) -> Result<()> {
-        assert!(self.socket.is_some());
-        if let Some(socket) = self.socket.as_mut() {
-            // serialze command
-            let command = SmClientCommand::SetState(state.clone());
-            let encoded_command = bincode::serialize(&command)?;
-            socket.write_all(&encoded_command).await?;
-
-            // wait the result from server
-            let mut buffer = vec![0; 1024];
-
-            match timeout(Duration::from_secs(1), socket.read(&mut buffer)).await {
-                Ok(Ok(n)) => {
-                    let response: SmResponse = bincode::deserialize(&buffer[..n])?;
-                    match response {
-                        SmResponse::SetState(response) => match response {
-                            Ok(_) => {}
-                            Err(error) => {
-                                return Err(error.into());
-                            }
-                        },
-                        _ => {
-                            panic!("Invalid response for SetState");
-                        }
-                    }
-                }
-                Ok(Err(_)) => {
-                    // error on read
-                    return Err(SetStateError::CommunicationError.into());
-                }
-                Err(_) => {
-                    // timeout
-                    return Err(SetStateError::CommunicationError.into());
-                }
-            }
+impl RetryPolicy {
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            interval: Duration::ZERO,
         }
-
-        Ok(())
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tokio::net::UnixListener;
-
-    #[tokio::test]
-    async fn get_initial_machine_state_transition() {
-        let domain_socket_path = std::env::temp_dir().join("test_domain_socket1");
-        let cloned_socket_path = domain_socket_path.clone();
-
-        let handle = tokio::spawn(async move {
-            if tokio::fs::metadata(&cloned_socket_path).await.is_ok() {
-                tokio::fs::remove_file(&cloned_socket_path).await.unwrap();
-            }
-
-            let listener = UnixListener::bind(&cloned_socket_path).unwrap();
-            let (mut stream, _) = listener.accept().await.unwrap();
+pub type UndefinedStateCallback = Arc<dyn Fn(ExecutionErrorEvent) + Send + Sync>;
 
-            let mut buffer = vec![0; 1024];
-            loop {
-                match stream.read(&mut buffer).await {
-                    Ok(len) => {
-                        assert!(len > 0);
-                        let request_command =
-                            bincode::deserialize::<SmClientCommand>(&buffer).unwrap();
-                        match request_command {
-                            SmClientCommand::GetInitialState => {
-                                let response = SmResponse::GetInitialState(Result::Ok(()));
-                                let serialized_resonse = bincode::serialize(&response).unwrap();
-                                stream.write(&serialized_resonse).await.unwrap();
-                            }
-                            SmClientCommand::SetState(_fg_state) => {
-                                assert!(false);
-                            }
-                        }
-                    }
-                    Err(error) => {
-                        panic!("error on read with '{:?}'", error);
-                    }
-                }
-                break;
-            }
-        });
+pub struct StateClientBuilder {
+    socket_path: PathBuf,
+    response_timeout: Duration,
+    retry_policy: RetryPolicy,
+}
 
-        // wait a second to create domain socket
-        tokio::time::sleep(Duration::from_millis(10)).await;
+impl Default for StateClientBuilder {
+    fn default() -> Self {
+        Self {
+            socket_path: PathBuf::from(OARA_SM_DOMAIN_SOCKET),
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+}
 
-        let mut state_client = StateClient::new();
-        state_client.connect(&domain_socket_path).await.unwrap();
+impl StateClientBuilder {
+    /// domain socket of Execution Management, `OARA_SM_DOMAIN_SOCKET` if not given
+    pub fn socket_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.socket_path = path.as_ref().to_path_buf();
+        self
+    }
 
-        let result = state_client
-            .get_initial_machine_state_transition_result()
-            .await;
-        assert!(result.is_ok());
+    /// how long a request waits for its response, `DEFAULT_RESPONSE_TIMEOUT` if not given
+    pub fn response_timeout(mut self, response_timeout: Duration) -> Self {
+        self.response_timeout = response_timeout;
+        self
+    }
 
-        handle.await.unwrap();
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
     }
 
-    #[tokio::test]
-    async fn get_initial_machine_state_transition_failure() {
-        let domain_socket_path = std::env::temp_dir().join("test_domain_socket2");
-        let cloned_socket_path = domain_socket_path.clone();
+    /// see `StateClient::create`
+    pub async fn create<F>(self, undefined_state_callback: F) -> Result<StateClient>
+    where
+        F: Fn(ExecutionErrorEvent) + Send + Sync + 'static,
+    {
+        let inner = Inner {
+            socket_path: self.socket_path,
+            response_timeout: self.response_timeout,
+            retry_policy: self.retry_policy,
+            undefined_state_callback: Arc::new(undefined_state_callback),
+            next_id: AtomicU64::new(0),
+            connection: Mutex::new(None),
+        };
+
+        let connection = inner
+            .connect()
+            .await
+            .ok_or(StateClientError::CommunicationError)?;
+        *inner.connection.lock().await = Some(connection);
+
+        Ok(StateClient {
+            inner: Arc::new(inner),
+        })
+    }
+}
 
-        let handle = tokio::spawn(async move {
-            if tokio::fs::metadata(&cloned_socket_path).await.is_ok() {
-                tokio::fs::remove_file(&cloned_socket_path).await.unwrap();
-            }
+// request id / waiting request
+type PendingHashMap = HashMap<u64, oneshot::Sender<SmResponse>>;
 
-            let listener = UnixListener::bind(&cloned_socket_path).unwrap();
-            let (mut stream, _) = listener.accept().await.unwrap();
+/// A connection to EM, responses are dispatched by a reader task
+struct Connection {
+    writer: OwnedWriteHalf,
+    // None once the reader stopped, e.g. EM restarted
+    pending: Arc<std::sync::Mutex<Option<PendingHashMap>>>,
+    reader: JoinHandle<()>,
+}
 
-            let mut buffer = vec![0; 1024];
-            loop {
-                match stream.read(&mut buffer).await {
-                    Ok(len) => {
-                        assert!(len > 0);
-                        let request_command =
-                            bincode::deserialize::<SmClientCommand>(&buffer).unwrap();
-                        match request_command {
-                            SmClientCommand::GetInitialState => {
-                                let response = SmResponse::GetInitialState(Err(InitialStateError::FailedInitializeInitialState.into()));
-                                let serialized_resonse = bincode::serialize(&response).unwrap();
-                                stream.write(&serialized_resonse).await.unwrap();
-                            }
-                            SmClientCommand::SetState(_fg_state) => {
-                                assert!(false);
-                            }
+impl Connection {
+    async fn open(
+        socket_path: &Path,
+        undefined_state_callback: UndefinedStateCallback,
+    ) -> std::io::Result<Self> {
+        let (mut read_half, writer) = UnixStream::connect(socket_path).await?.into_split();
+        let pending = Arc::new(std::sync::Mutex::new(Some(PendingHashMap::new())));
+
+        let cloned_pending = pending.clone();
+        let reader = tokio::spawn(async move {
+            // closed by EM or malformed
+            while let Ok(message) = read_frame::<_, SmMessage>(&mut read_half).await {
+                match message {
+                    SmMessage::Response { id, response } => {
+                        let sender = cloned_pending
+                            .lock()
+                            .unwrap()
+                            .as_mut()
+                            .and_then(|pending| pending.remove(&id));
+                        // the request may have timed out
+                        if let Some(sender) = sender {
+                            let _ = sender.send(response);
                         }
                     }
-                    Err(error) => {
-                        panic!("error on read with '{:?}'", error);
+                    SmMessage::ExecutionErrorEvent(event) => {
+                        // not to block the responses
+                        let callback = undefined_state_callback.clone();
+                        tokio::spawn(async move { callback(event) });
                     }
                 }
-                break;
             }
+            // waiting requests fail with CommunicationError
+            cloned_pending.lock().unwrap().take();
         });
 
-        // wait a second to create domain socket
-        tokio::time::sleep(Duration::from_millis(10)).await;
+        Ok(Self {
+            writer,
+            pending,
+            reader,
+        })
+    }
 
-        let mut state_client = StateClient::new();
-        state_client.connect(&domain_socket_path).await.unwrap();
+    fn is_alive(&self) -> bool {
+        self.pending.lock().unwrap().is_some()
+    }
 
-        let result = state_client
-            .get_initial_machine_state_transition_result()
-            .await;
+    /// None if the connection is broken
+    async fn send(&mut self, request: &SmRequest) -> Option<oneshot::Receiver<SmResponse>> {
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().as_mut()?.insert(request.id, sender);
 
-        let error = result.err().unwrap();
-        let initial_state_error = error.downcast_ref::<InitialStateError>().unwrap();
-        assert_eq!(
-            initial_state_error,
-            &InitialStateError::FailedInitializeInitialState
-        );
+        if write_frame(&mut self.writer, request).await.is_err() {
+            if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+                pending.remove(&request.id);
+            }
+            return None;
+        }
+        Some(receiver)
+    }
+}
 
-        handle.await.unwrap();
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.reader.abort();
     }
+}
 
-    #[tokio::test]
-    async fn set_state() {
-        let domain_socket_path = std::env::temp_dir().join("test_domain_socket3");
-        let cloned_socket_path = domain_socket_path.clone();
+struct Inner {
+    socket_path: PathBuf,
+    response_timeout: Duration,
+    retry_policy: RetryPolicy,
+    undefined_state_callback: UndefinedStateCallback,
+    next_id: AtomicU64,
+    connection: Mutex<Option<Connection>>,
+}
 
-        let handle = tokio::spawn(async move {
-            if tokio::fs::metadata(&cloned_socket_path).await.is_ok() {
-                tokio::fs::remove_file(&cloned_socket_path).await.unwrap();
+impl Inner {
+    /// None if every attempt of the retry policy failed
+    async fn connect(&self) -> Option<Connection> {
+        let max_attempts = self.retry_policy.max_attempts.max(1);
+        for attempt in 1..=max_attempts {
+            let callback = self.undefined_state_callback.clone();
+            if let Ok(connection) = Connection::open(&self.socket_path, callback).await {
+                return Some(connection);
             }
+            if attempt < max_attempts {
+                sleep(self.retry_policy.interval).await;
+            }
+        }
+        None
+    }
 
-            let listener = UnixListener::bind(&cloned_socket_path).unwrap();
-            let (mut stream, _) = listener.accept().await.unwrap();
-
-            let mut buffer = vec![0; 1024];
-            loop {
-                match stream.read(&mut buffer).await {
-                    Ok(len) => {
-                        assert!(len > 0);
-                        let request_command =
-                            bincode::deserialize::<SmClientCommand>(&buffer).unwrap();
-                        match request_command {
-                            SmClientCommand::GetInitialState => {
-                                assert!(false);
-                            }
-                            SmClientCommand::SetState(fg_state) => {
-                                assert_eq!(fg_state, FunctionGroupState{
-                                    function_group: "MachineFg".to_owned(),
-                                    function_group_state: "Startup".to_owned(),
-                                });
-
-                                let response = SmResponse::SetState(Ok(()));
-                                let serialized_resonse = bincode::serialize(&response).unwrap();
-                                stream.write(&serialized_resonse).await.unwrap();
-                            }
-                        }
-                    }
-                    Err(error) => {
-                        panic!("error on read with '{:?}'", error);
-                    }
+    /// None on communication error
+    async fn request(&self, command: SmClientCommand) -> Option<SmResponse> {
+        let request = SmRequest {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            command,
+        };
+
+        let receiver = {
+            let mut connection = self.connection.lock().await;
+            let mut receiver = None;
+            // once more on a new connection if EM closed the current one, e.g. EM restarted
+            for _ in 0..2 {
+                if !connection.as_ref().is_some_and(Connection::is_alive) {
+                    *connection = Some(self.connect().await?);
                 }
-                break;
+                receiver = connection.as_mut()?.send(&request).await;
+                if receiver.is_some() {
+                    break;
+                }
+                *connection = None;
             }
-        });
-
-        // wait a second to create domain socket
-        tokio::time::sleep(Duration::from_millis(10)).await;
-
-        let mut state_client = StateClient::new();
-        state_client.connect(&domain_socket_path).await.unwrap();
-
-        let fg_state = FunctionGroupState::new("MachineFg".to_owned(), "Startup".to_owned());
-        let result = state_client.set_state(&fg_state).await;
-        assert!(result.is_ok());
-
-        handle.await.unwrap();
+            receiver?
+        };
+
+        // the connection is not locked while waiting, other requests can be sent
+        match timeout(self.response_timeout, receiver).await {
+            Ok(Ok(response)) => Some(response),
+            // timeout, or the connection is broken
+            _ => None,
+        }
     }
 }
 
-/*use super::execution_client::ExecutionClientError;
-use super::FunctionGroupState;
-
-#[derive(Debug, Error)]
-enum StateClientError {
-    #[error("cancelled by a newer request")]
-    kCancelled,
-    #[error("transition to the requested Function Group state failed")]
-    kFailed,
-    #[error("Unexpected Termination in Process of target Function Group State happened")]
-    kFailedUnexpectedTerminationOnEnter,
-    #[error("can’t communicate with Execution Management")]
-    kCommunicationError,
-    #[error("transition to the requested state is prohibited, or invalid state")]
-    kInvalidTransition,
-    #[error("an integrity or authenticity check failed during state transition")]
-    kIntegrityOrAuthenticity,
-    #[error("One of the processes terminated in an unexpected way during the state transition")]
-    kFailedUnexpectedTermination,
-    #[error("The given Function Group State couldn’t be found in the ProcessedManifest")]
-    kMetaModelError,
-}
-
 /// Symbol: StateClient
 /// Syntax: class StateClient final {...};
 /// Description: StateClient is an interface of Execution Management that is used by State Management to
@@ -432,13 +347,20 @@ enum StateClientError {
 /// should have rights to use it. To eventually implement the Named Constructor Idiom, the
 /// developer may either make the default constructor private or delete it and define a non-default
 /// constructor.
+///
+/// A cloned StateClient shares the connection, so it can be used by several tasks at once.
+/// The connection is opened again on the next request if EM closed it, events pushed by EM in the
+/// meantime are lost.
 #[derive(Clone)]
-struct StateClient {
-
-    undefined_state_callback: Box<dyn Fn(ExecutionClientError)>
+pub struct StateClient {
+    inner: Arc<Inner>,
 }
 
 impl StateClient {
+    pub fn builder() -> StateClientBuilder {
+        StateClientBuilder::default()
+    }
+
     /// [SWS_EM_02561]{DRAFT} Definition of API function ara::exec::StateClient::StateClient
     /// Symbol: StateClient(std::function< void(const ara::exec::ExecutionErrorEvent &)> undefinedState
     /// Callback)
@@ -451,29 +373,13 @@ impl StateClient {
     /// the callback in form of ExecutionErrorEvent
     /// Errors: ara::exec::ExecErrc::kCommunicationError communication error occurred
     /// Description: Regular constructor for StateClient.
-    pub fn new(undefined_state_callback: Box<dyn Fn(ExecutionClientError)>) -> Result<Self> {
-
-        let listener = UnixListener::bind(socket_path)?;
-        Self {
-            undefined_state_callback,
-        }
-    }
-
-    /// [SWS_EM_02276] Definition of API function ara::exec::StateClient::Create
-    /// Symbol: Create(std::function< void(const ara::exec::ExecutionErrorEvent &)> undefinedStateCallback)
-    /// Syntax: static ara::core::Result< StateClient > Create (std::function<
-    /// void(const ara::exec::ExecutionErrorEvent &)> undefinedStateCallback) noexcept;
-    /// Parameters (in): undefinedStateCallback callback to be invoked by StateClient library if a FunctionGroup
-    /// changes its state unexpectedly to an Undefined Function Group
-    /// State, i.e. without previous request by SetState(). The affected
-    /// FunctionGroup and ExecutionError is provided as an argument to
-    /// the callback in form of ExecutionErrorEvent.
-    /// Return value: ara::core::Result< StateClient > a result that contains either a StateClient object or an error.
-    /// Errors: ara::exec::ExecErrc::kCommunicationError communication error occurred
-    /// Description: Named constructor for StateClient.
-    /// Notes: This named constructor may call a private constructor defined by the developer.
-    pub fn create(undefined_state_callback: Box<dyn Fn(ExecutionClientError)>) -> Result<Self> {
-        StateClient::new(undefined_state_callback)
+    ///
+    /// It connects to `OARA_SM_DOMAIN_SOCKET` with the default settings, see `builder()` for others
+    pub async fn create<F>(undefined_state_callback: F) -> Result<Self>
+    where
+        F: Fn(ExecutionErrorEvent) + Send + Sync + 'static,
+    {
+        Self::builder().create(undefined_state_callback).await
     }
 
     /// [SWS_EM_02278] Definition of API function ara::exec::StateClient::SetState
@@ -505,9 +411,16 @@ impl StateClient {
     /// Description: Method to request state transition for a single Function Group.
     /// This method will request Execution Management to perform state transition and return
     /// immediately. Returned ara::core::Future can be used to determine result of requested transition.
-    pub async fn set_state(state: &FunctionGroupState) -> Result<()> {
-
-        Ok(())
+    pub async fn set_state(&self, state: &FunctionGroupState) -> Result<()> {
+        match self
+            .inner
+            .request(SmClientCommand::SetState(state.clone()))
+            .await
+        {
+            Some(SmResponse::SetState(response)) => Ok(response?),
+            // error on read, malformed, unexpected response or timeout
+            _ => Err(SetStateError::CommunicationError.into()),
+        }
     }
 
     /// [SWS_EM_02279] Definition of API function ara::exec::StateClient::GetInitialMachineStateTransitionResult
@@ -525,12 +438,15 @@ impl StateClient {
     /// ara::exec::ExecErrc::kCommunicationError
     ///   if StateClient can’t communicate with Execution Management (e.g.IPC link is down)
     /// Description: Method to retrieve result of Machine State initial transition to Startup state.
-    /// TBD : errors..
     /// Notes: This method allows State Management to retrieve the result of a transition specified by SWS_
     /// EM_01023 and SWS_EM_02241. Please note that this transition happens once per machine life
     /// cycle, thus the result delivered by this method shall not change (unless machine is started again).
-    pub async fn get_initial_machine_state_transition_result() -> Result<()> {
-        Ok(())
+    pub async fn get_initial_machine_state_transition_result(&self) -> Result<()> {
+        match self.inner.request(SmClientCommand::GetInitialState).await {
+            Some(SmResponse::GetInitialState(response)) => Ok(response?),
+            // error on read, malformed, unexpected response or timeout
+            _ => Err(InitialStateError::CommunicationError.into()),
+        }
     }
 
     /// Symbol: GetExecutionError(const ara::exec::FunctionGroupState &functionGroupState)
@@ -549,14 +465,414 @@ impl StateClient {
     ///   State to an Undefined Function Group State.
     ///   This function will return with error and will not return an ExecutionErrorEvent object, if the
     ///   Function Group is in a defined Function Group state again.
-    /// TBD : errors
-    pub fn get_execution_error(function_group_state: &FunctionGroupState) -> Result<ExecutionErrorEvent> {
-        Ok(ExecutionClientError::kCommunicationError)
+    ///
+    /// Only the Function Group is of interest, so it is given instead of a Function Group State
+    pub async fn get_execution_error(&self, function_group: &str) -> Result<ExecutionErrorEvent> {
+        let command = SmClientCommand::GetExecutionError(function_group.to_owned());
+        match self.inner.request(command).await {
+            Some(SmResponse::GetExecutionError(response)) => Ok(response?),
+            // error on read, malformed, unexpected response or timeout
+            _ => Err(GetExecutionErrorError::CommunicationError.into()),
+        }
     }
 
-    // TBD
-    // [SWS_EM_02543]{DRAFT} Default value for ExecutionError dIn case of Unexpected
-    // Termination or Unexpected Self-termination of a Modelled
-    // Process which does not have an executionError configured, Execution Management
-    // shall report the ExecutionError value 1.c(RS_EM_00101)
-}*/
+    /// Not in AUTOSAR, status of a process of the execution manifests, e.g. how many times EM
+    /// restarted it
+    pub async fn get_process_status(&self, process: &str) -> Result<ProcessStatus> {
+        let command = SmClientCommand::GetProcessStatus(process.to_owned());
+        match self.inner.request(command).await {
+            Some(SmResponse::GetProcessStatus(response)) => Ok(response?),
+            // error on read, malformed, unexpected response or timeout
+            _ => Err(GetProcessStatusError::CommunicationError.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::FrameError;
+    use crate::function_group::MACHINE_FG;
+    use tokio::net::UnixListener;
+
+    fn bind(socket_path: &Path) -> UnixListener {
+        if socket_path.exists() {
+            std::fs::remove_file(socket_path).unwrap();
+        }
+        UnixListener::bind(socket_path).unwrap()
+    }
+
+    /// mock of EM, answers requests of the connection in order until `count` requests are served
+    async fn serve<F>(stream: &mut UnixStream, count: usize, respond: F)
+    where
+        F: Fn(SmClientCommand) -> SmResponse,
+    {
+        for _ in 0..count {
+            let request = match read_frame::<_, SmRequest>(stream).await {
+                Ok(request) => request,
+                Err(FrameError::Closed) => return,
+                Err(error) => panic!("error on read with '{:?}'", error),
+            };
+            let message = SmMessage::Response {
+                id: request.id,
+                response: respond(request.command),
+            };
+            write_frame(stream, &message).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn get_initial_machine_state_transition() {
+        let domain_socket_path = std::env::temp_dir().join("test_domain_socket1");
+        let listener = bind(&domain_socket_path);
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            serve(&mut stream, 1, |command| match command {
+                SmClientCommand::GetInitialState => SmResponse::GetInitialState(Ok(())),
+                _ => unreachable!(),
+            })
+            .await;
+        });
+
+        let state_client = StateClient::builder()
+            .socket_path(&domain_socket_path)
+            .create(|_| {})
+            .await
+            .unwrap();
+
+        let result = state_client
+            .get_initial_machine_state_transition_result()
+            .await;
+        assert!(result.is_ok());
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_initial_machine_state_transition_failure() {
+        let domain_socket_path = std::env::temp_dir().join("test_domain_socket2");
+        let listener = bind(&domain_socket_path);
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            serve(&mut stream, 1, |command| match command {
+                SmClientCommand::GetInitialState => SmResponse::GetInitialState(Err(
+                    InitialStateError::FailedInitializeInitialState,
+                )),
+                _ => unreachable!(),
+            })
+            .await;
+        });
+
+        let state_client = StateClient::builder()
+            .socket_path(&domain_socket_path)
+            .create(|_| {})
+            .await
+            .unwrap();
+
+        let result = state_client
+            .get_initial_machine_state_transition_result()
+            .await;
+
+        let error = result.err().unwrap();
+        let initial_state_error = error.downcast_ref::<InitialStateError>().unwrap();
+        assert_eq!(
+            initial_state_error,
+            &InitialStateError::FailedInitializeInitialState
+        );
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn set_state() {
+        let domain_socket_path = std::env::temp_dir().join("test_domain_socket3");
+        let listener = bind(&domain_socket_path);
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            serve(&mut stream, 1, |command| match command {
+                SmClientCommand::SetState(fg_state) => {
+                    assert_eq!(
+                        fg_state,
+                        FunctionGroupState {
+                            function_group: MACHINE_FG.to_owned(),
+                            function_group_state: "Startup".to_owned(),
+                        }
+                    );
+                    SmResponse::SetState(Ok(()))
+                }
+                _ => unreachable!(),
+            })
+            .await;
+        });
+
+        let state_client = StateClient::builder()
+            .socket_path(&domain_socket_path)
+            .create(|_| {})
+            .await
+            .unwrap();
+
+        let fg_state = FunctionGroupState::new(MACHINE_FG.to_owned(), "Startup".to_owned());
+        let result = state_client.set_state(&fg_state).await;
+        assert!(result.is_ok());
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_execution_error() {
+        let domain_socket_path = std::env::temp_dir().join("test_domain_socket8");
+        let listener = bind(&domain_socket_path);
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            serve(&mut stream, 3, |command| match command {
+                SmClientCommand::GetExecutionError(function_group) => {
+                    SmResponse::GetExecutionError(match function_group.as_str() {
+                        "FG1" => Ok(ExecutionErrorEvent {
+                            execution_error: 7,
+                            function_group,
+                        }),
+                        "FG2" => Err(GetExecutionErrorError::Failed),
+                        _ => Err(GetExecutionErrorError::MetamodelError),
+                    })
+                }
+                _ => unreachable!(),
+            })
+            .await;
+        });
+
+        let state_client = StateClient::builder()
+            .socket_path(&domain_socket_path)
+            .create(|_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(
+            state_client.get_execution_error("FG1").await.unwrap(),
+            ExecutionErrorEvent {
+                execution_error: 7,
+                function_group: "FG1".to_owned(),
+            }
+        );
+        for (function_group, expected) in [
+            ("FG2", GetExecutionErrorError::Failed),
+            ("FG3", GetExecutionErrorError::MetamodelError),
+        ] {
+            let error = state_client
+                .get_execution_error(function_group)
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(
+                error.downcast_ref::<GetExecutionErrorError>().unwrap(),
+                &expected
+            );
+        }
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn get_process_status() {
+        let domain_socket_path = std::env::temp_dir().join("test_domain_socket9");
+        let listener = bind(&domain_socket_path);
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            serve(&mut stream, 2, |command| match command {
+                SmClientCommand::GetProcessStatus(process) => {
+                    SmResponse::GetProcessStatus(match process.as_str() {
+                        "APP1" => Ok(ProcessStatus {
+                            process,
+                            pid: Some(100),
+                            restart_count: 2,
+                        }),
+                        _ => Err(GetProcessStatusError::MetamodelError),
+                    })
+                }
+                _ => unreachable!(),
+            })
+            .await;
+        });
+
+        let state_client = StateClient::builder()
+            .socket_path(&domain_socket_path)
+            .create(|_| {})
+            .await
+            .unwrap();
+
+        assert_eq!(
+            state_client.get_process_status("APP1").await.unwrap(),
+            ProcessStatus {
+                process: "APP1".to_owned(),
+                pid: Some(100),
+                restart_count: 2,
+            }
+        );
+        let error = state_client.get_process_status("APP2").await.err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<GetProcessStatusError>().unwrap(),
+            &GetProcessStatusError::MetamodelError
+        );
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests() {
+        let domain_socket_path = std::env::temp_dir().join("test_domain_socket4");
+        let listener = bind(&domain_socket_path);
+
+        // answers two requests in the reversed order
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let first = read_frame::<_, SmRequest>(&mut stream).await.unwrap();
+            let second = read_frame::<_, SmRequest>(&mut stream).await.unwrap();
+            for request in [second, first] {
+                let response = match request.command {
+                    SmClientCommand::SetState(fg_state) if fg_state.function_group == "FG1" => {
+                        SmResponse::SetState(Ok(()))
+                    }
+                    _ => SmResponse::SetState(Err(SetStateError::Failed)),
+                };
+                let message = SmMessage::Response {
+                    id: request.id,
+                    response,
+                };
+                write_frame(&mut stream, &message).await.unwrap();
+            }
+        });
+
+        let state_client = StateClient::builder()
+            .socket_path(&domain_socket_path)
+            .create(|_| {})
+            .await
+            .unwrap();
+
+        let mut tasks = Vec::new();
+        for fg in ["FG1", "FG2"] {
+            let state_client = state_client.clone();
+            tasks.push(tokio::spawn(async move {
+                let fg_state = FunctionGroupState::new(fg.to_owned(), "On".to_owned());
+                state_client
+                    .set_state(&fg_state)
+                    .await
+                    .map_err(|error| error.downcast::<SetStateError>().unwrap())
+            }));
+        }
+        let mut results = Vec::new();
+        for task in tasks {
+            results.push(task.await.unwrap());
+        }
+        assert_eq!(results, vec![Ok(()), Err(SetStateError::Failed)]);
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn reconnect() {
+        let domain_socket_path = std::env::temp_dir().join("test_domain_socket5");
+        let listener = bind(&domain_socket_path);
+
+        let cloned_socket_path = domain_socket_path.clone();
+        let handle = tokio::spawn(async move {
+            let respond = |_| SmResponse::GetInitialState(Ok(()));
+            let (mut stream, _) = listener.accept().await.unwrap();
+            serve(&mut stream, 1, respond).await;
+
+            // EM restarts
+            drop(stream);
+            drop(listener);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            let listener = bind(&cloned_socket_path);
+            let (mut stream, _) = listener.accept().await.unwrap();
+            serve(&mut stream, 1, respond).await;
+        });
+
+        let state_client = StateClient::builder()
+            .socket_path(&domain_socket_path)
+            .retry_policy(RetryPolicy {
+                max_attempts: 20,
+                interval: Duration::from_millis(50),
+            })
+            .create(|_| {})
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            state_client
+                .get_initial_machine_state_transition_result()
+                .await
+                .unwrap();
+        }
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn undefined_state_callback() {
+        let domain_socket_path = std::env::temp_dir().join("test_domain_socket7");
+        let listener = bind(&domain_socket_path);
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let event = ExecutionErrorEvent {
+                execution_error: 3,
+                function_group: "FG1".to_owned(),
+            };
+            write_frame(&mut stream, &SmMessage::ExecutionErrorEvent(event))
+                .await
+                .unwrap();
+            // responses still work
+            serve(&mut stream, 1, |_| SmResponse::GetInitialState(Ok(()))).await;
+        });
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let state_client = StateClient::builder()
+            .socket_path(&domain_socket_path)
+            .create(move |event| sender.send(event).unwrap())
+            .await
+            .unwrap();
+
+        let event = timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            event,
+            ExecutionErrorEvent {
+                execution_error: 3,
+                function_group: "FG1".to_owned(),
+            }
+        );
+        state_client
+            .get_initial_machine_state_transition_result()
+            .await
+            .unwrap();
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn create_without_em() {
+        let domain_socket_path = std::env::temp_dir().join("test_domain_socket6");
+        if domain_socket_path.exists() {
+            std::fs::remove_file(&domain_socket_path).unwrap();
+        }
+
+        let result = StateClient::builder()
+            .socket_path(&domain_socket_path)
+            .retry_policy(RetryPolicy::never())
+            .create(|_| {})
+            .await;
+        let error = result.err().unwrap();
+        assert_eq!(
+            error.downcast_ref::<StateClientError>().unwrap(),
+            &StateClientError::CommunicationError
+        );
+    }
+}